@@ -0,0 +1,145 @@
+use crate::config;
+use crate::error::{AppError, Result};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::error;
+
+/// A track detected during this session
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RecentTrack {
+    pub title: String,
+    pub artist: String,
+    pub album: Option<String>,
+    pub apple_music_url: String,
+    /// UNIX time at which the track was detected
+    pub played_at: i64,
+}
+
+impl RecentTrack {
+    /// Create a history entry for a track detected now
+    pub fn new(title: &str, artist: &str, album: Option<&str>, apple_music_url: &str) -> Self {
+        RecentTrack {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: album.map(str::to_string),
+            apple_music_url: apple_music_url.to_string(),
+            played_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64,
+        }
+    }
+
+    /// Whether both entries are the same track, regardless of when they were played
+    fn is_same_track(&self, other: &RecentTrack) -> bool {
+        self.title == other.title && self.artist == other.artist
+    }
+}
+
+// Bounded history of the tracks detected this session, newest first
+lazy_static::lazy_static! {
+    static ref RECENT_TRACKS: Mutex<VecDeque<RecentTrack>> = Mutex::new(VecDeque::new());
+}
+
+/// Lock the history mutex
+fn lock_history() -> Result<std::sync::MutexGuard<'static, VecDeque<RecentTrack>>> {
+    RECENT_TRACKS
+        .lock()
+        .map_err(|e| AppError::Application(format!("Failed to lock history mutex: {}", e)))
+}
+
+/// Record a newly detected track, returning whether the history changed
+pub fn push(track: RecentTrack) -> bool {
+    let mut guard = match lock_history() {
+        Ok(guard) => guard,
+        Err(e) => {
+            error!("{}", e);
+            return false;
+        }
+    };
+
+    // Don't record the same track twice in a row
+    if guard.front().is_some_and(|last| last.is_same_track(&track)) {
+        return false;
+    }
+
+    guard.push_front(track);
+    guard.truncate(config::get().history_limit);
+    true
+}
+
+/// Get the most recently detected tracks, newest first
+pub fn recent() -> Vec<RecentTrack> {
+    match lock_history() {
+        Ok(guard) => guard
+            .iter()
+            .take(config::get().recent_tracks_limit)
+            .cloned()
+            .collect(),
+        Err(e) => {
+            error!("{}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Get up to `limit` of the tracks detected this session, newest first
+pub fn latest(limit: usize) -> Result<Vec<RecentTrack>> {
+    Ok(lock_history()?.iter().take(limit).cloned().collect())
+}
+
+/// Forget every track detected so far
+pub fn clear() -> Result<()> {
+    lock_history()?.clear();
+    Ok(())
+}
+
+/// Quote a CSV field when needed
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Export the session history to a file, as CSV for `.csv` paths and JSON otherwise
+/// Returns the number of exported tracks
+pub fn export(path: &Path) -> Result<usize> {
+    // Oldest first reads naturally in a log
+    let tracks: Vec<RecentTrack> = lock_history()?.iter().rev().cloned().collect();
+
+    let is_csv = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+
+    let contents = if is_csv {
+        let mut csv = String::from("played_at,title,artist,album,apple_music_url\n");
+        for track in &tracks {
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                track.played_at,
+                csv_field(&track.title),
+                csv_field(&track.artist),
+                csv_field(track.album.as_deref().unwrap_or_default()),
+                csv_field(&track.apple_music_url),
+            ));
+        }
+        csv
+    } else {
+        serde_json::to_string_pretty(&tracks)?
+    };
+
+    std::fs::write(path, contents).map_err(|e| {
+        AppError::Application(format!(
+            "Failed to write history to {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+
+    Ok(tracks.len())
+}
@@ -1,102 +1,519 @@
 use crate::apple_music::player;
 use crate::commands::start_discord_presence;
-use crate::config::constants::APPLE_MUSIC_URL;
-use std::time::Duration;
+use crate::config;
+use crate::discord;
+use crate::error::{AppError, Result};
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use std::collections::VecDeque;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
 
-/// Open Apple Music in app mode using a compatible browser
-pub fn open_apple_music() {
-    println!("Opening Apple Music in app mode...");
-
-    // Determine which browser to use (chromium or brave)
-    let browsers = ["chromium", "brave", "brave-browser"];
-    let mut browser_cmd = String::new();
-
-    for browser in browsers {
-        // Check if browser is installed
-        if let Ok(output) = std::process::Command::new("which").arg(browser).output() {
-            if !output.stdout.is_empty() {
-                browser_cmd = browser.to_string();
-                println!("Found browser: {}", browser_cmd);
-                break;
+/// Time given to the browser to exit after SIGTERM, in seconds
+const TERMINATE_TIMEOUT_SECS: u64 = 2;
+
+/// Relaunches allowed within the relaunch window, so a crash loop can't spawn browsers endlessly
+const MAX_RELAUNCHES: usize = 3;
+
+/// Window the relaunches are counted in, in seconds
+const RELAUNCH_WINDOW_SECS: u64 = 60;
+
+/// Interval between checks for the player of a freshly launched browser, in milliseconds
+const READY_POLL_INTERVAL_MS: u64 = 500;
+
+// Keep the spawned browser process so it can be killed and reaped on quit
+lazy_static::lazy_static! {
+    static ref APPLE_MUSIC_CHILD: Mutex<Option<Child>> = Mutex::new(None);
+    static ref RELAUNCH_TIMES: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+}
+
+// Set once we kill Apple Music on quit, so its shutdown isn't mistaken for a closed window
+static QUITTING: AtomicBool = AtomicBool::new(false);
+
+// Set while Apple Music is restarted on purpose, for the same reason
+static RESTARTING: AtomicBool = AtomicBool::new(false);
+
+/// Window class argument we launch Apple Music with, telling its browser apart from the user's
+const APP_CLASS_ARG: &str = "--class=AppleMusic";
+
+/// Arguments of Chromium-based browsers, opening the page as a standalone app window
+const CHROMIUM_ARGS: &[&str] = &["--app={url}", "--no-first-run", APP_CLASS_ARG];
+
+/// Arguments of Firefox, which has no app mode and opens a dedicated window instead
+const FIREFOX_ARGS: &[&str] = &["--new-window", APP_CLASS_ARG, "{url}"];
+
+/// How to launch a browser so Apple Music opens in its own window with MPRIS enabled
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct BrowserLauncher {
+    /// Binary name, looked up on the PATH
+    binary: String,
+    /// Argument template, `{url}` is replaced by the Apple Music URL
+    args: &'static [&'static str],
+    /// Flags helping the browser expose its media session over MPRIS
+    mpris_flags: &'static [&'static str],
+}
+
+impl BrowserLauncher {
+    /// Map a browser binary name to its argument set, unknown browsers are assumed Chromium-based
+    fn for_binary(binary: &str) -> Self {
+        let (args, mpris_flags): (&'static [&'static str], &'static [&'static str]) =
+            if binary.starts_with("firefox") {
+                // Firefox registers on MPRIS out of the box
+                (FIREFOX_ARGS, &[])
+            } else if binary.starts_with("brave") {
+                // Brave keeps hardware media key handling, which drives MPRIS, behind a feature
+                (
+                    CHROMIUM_ARGS,
+                    &["--enable-features=HardwareMediaKeyHandling"],
+                )
+            } else {
+                // MediaSessionService is enabled by default on current Chromium, Chrome and Vivaldi
+                (CHROMIUM_ARGS, &[])
+            };
+
+        BrowserLauncher {
+            binary: binary.to_string(),
+            args,
+            mpris_flags,
+        }
+    }
+
+    /// Find the first installed browser, in preference order
+    fn find_installed(browsers: &[String]) -> Option<Self> {
+        for browser in browsers {
+            // Check if browser is installed
+            let Ok(output) = std::process::Command::new("which").arg(browser).output() else {
+                continue;
+            };
+            let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+            // `which` may print an alias or a path that can't actually be run
+            if !path.is_empty() && is_executable(Path::new(&path)) {
+                info!("Found browser: {} ({})", browser, path);
+                return Some(Self::for_binary(browser));
+            } else if !path.is_empty() {
+                warn!("Skipping {}: {} is not an executable file", browser, path);
             }
         }
+
+        None
     }
 
-    if browser_cmd.is_empty() {
-        eprintln!("No compatible browser found. Please install Chromium or Brave.");
-        return;
+    /// Arguments opening the given URL, followed by the MPRIS flags and the configured extra ones
+    fn args(&self, url: &str) -> Vec<String> {
+        let config = config::get();
+        let mpris_flags = config.mpris_flags_override.unwrap_or_else(|| {
+            self.mpris_flags
+                .iter()
+                .map(|flag| flag.to_string())
+                .collect()
+        });
+
+        self.args
+            .iter()
+            .map(|arg| arg.replace("{url}", url))
+            .chain(mpris_flags)
+            .chain(config.extra_browser_args)
+            .collect()
+    }
+}
+
+/// Store the spawned browser process handle
+fn store_child(child: Child) {
+    match APPLE_MUSIC_CHILD.lock() {
+        Ok(mut guard) => *guard = Some(child),
+        Err(e) => error!("Failed to lock child process mutex: {}", e),
+    }
+}
+
+/// Take the spawned browser process handle, if we still have it
+fn take_child() -> Option<Child> {
+    match APPLE_MUSIC_CHILD.lock() {
+        Ok(mut guard) => guard.take(),
+        Err(e) => {
+            error!("Failed to lock child process mutex: {}", e);
+            None
+        }
+    }
+}
+
+/// Check that a path is a regular file with an execute permission bit set
+fn is_executable(path: &Path) -> bool {
+    match std::fs::metadata(path) {
+        Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+/// Open Apple Music in app mode using a compatible browser
+pub fn open_apple_music() -> Result<()> {
+    // Adopt an instance that is already open instead of spawning a duplicate window
+    if let Some(pid) = find_existing_instance() {
+        info!("Adopting running Apple Music instance with PID {}", pid);
+        player::store_pid(pid)?;
+        discord::mark_session_start();
+        let _ = start_discord_presence();
+        return Ok(());
     }
 
+    info!("Opening Apple Music in app mode...");
+
+    // Determine which browser to use, in the configured preference order
+    let launcher =
+        BrowserLauncher::find_installed(&config::get().browsers).ok_or(AppError::NoBrowser)?;
+
     // Launch a new instance and store the child process
-    println!("Opening new Apple Music instance with {}", browser_cmd);
-    match std::process::Command::new(&browser_cmd)
-        .args([
-            format!("--app={}", APPLE_MUSIC_URL),
-            "--no-first-run".to_string(),
-            "--class=AppleMusic".to_string(),
-            // Add additional arguments to improve MPRIS compatibility
-            "--enable-features=MediaSessionService".to_string(),
-        ])
+    info!("Opening new Apple Music instance with {}", launcher.binary);
+    let args = launcher.args(&config::get().apple_music_url);
+    debug!("Running {} {}", launcher.binary, args.join(" "));
+    let child = std::process::Command::new(&launcher.binary)
+        .args(&args)
+        // Lead a new process group so quitting can take the renderers down too
+        .process_group(0)
         .spawn()
-    {
-        Ok(child) => {
-            // Store the PID of our Apple Music instance
-            let pid = child.id();
-            println!("Apple Music launched with PID: {}", pid);
-
-            // Store the PID in our global variable thread-safely
-            if let Err(e) = player::store_pid(pid) {
-                eprintln!("Failed to store PID: {}", e);
-                return;
-            }
+        .map_err(|e| {
+            AppError::Application(format!(
+                "Failed to open Apple Music with {}: {}",
+                launcher.binary, e
+            ))
+        })?;
 
-            // Wait a bit for the browser to fully initialize
-            std::thread::sleep(Duration::from_secs(5));
+    // Store the PID of our Apple Music instance
+    let pid = child.id();
+    info!("Apple Music launched with PID: {}", pid);
+    player::store_pid(pid)?;
 
-            // Try to verify if MPRIS is working
-            match player::find_apple_music_player() {
-                Ok(player) => println!("Successfully verified MPRIS player: {}", player.identity()),
-                Err(e) => println!(
-                    "Note: Could not verify MPRIS player yet: {}. This is normal during startup.",
-                    e
-                ),
-            }
+    // Keep the handle so we can reap the process on quit
+    store_child(child);
 
-            // Start discord presence
-            let _ = start_discord_presence();
+    // The session starts with the browser
+    discord::mark_session_start();
+
+    // Start the presence once the browser exposes its player, without holding the caller up
+    std::thread::spawn(|| {
+        wait_for_player(Duration::from_secs(config::get().player_ready_timeout_secs));
+        let _ = start_discord_presence();
+    });
+
+    Ok(())
+}
+
+/// Poll for the MPRIS player of the launched browser until it shows up or the timeout passes
+/// Returns how long the player took to show up
+fn wait_for_player(timeout: Duration) -> Option<Duration> {
+    let started = Instant::now();
+
+    while started.elapsed() < timeout {
+        if player::find_apple_music_player().is_ok() {
+            let elapsed = started.elapsed();
+            info!(
+                "Apple Music player ready after {:.1}s",
+                elapsed.as_secs_f64()
+            );
+            return Some(elapsed);
         }
+        std::thread::sleep(Duration::from_millis(READY_POLL_INTERVAL_MS));
+    }
+
+    // The event listener still attaches whenever the player shows up later
+    info!(
+        "Apple Music player not ready after {}s, consider raising player_ready_timeout_secs",
+        timeout.as_secs()
+    );
+    None
+}
+
+/// Find the PID of an Apple Music instance that is already open, e.g. by a previous amusic run
+fn find_existing_instance() -> Option<u32> {
+    // MPRIS only lists the browser once its media session is active, so also check the process list
+    match player::find_any_apple_music_pid() {
+        Ok(Some(pid)) => Some(pid),
+        Ok(None) => find_running_pid(),
         Err(e) => {
-            eprintln!("Failed to open Apple Music with {}: {}", browser_cmd, e);
+            debug!("Could not scan MPRIS players: {}", e);
+            find_running_pid()
         }
     }
 }
 
-/// Kill Apple Music process
-pub fn kill_apple_music() {
-    let player = player::find_apple_music_player();
-    match player {
-        Ok(_) => {
-            // If we have the PID, use it to kill the process
-            match player::get_pid() {
-                Ok(pid) => {
-                    println!("Killing Apple Music process with PID: {}", pid);
-                    let _ = std::process::Command::new("kill")
-                        .arg(pid.to_string())
-                        .spawn();
-                }
-                Err(_) => {
-                    // Fallback to pkill if we don't have the PID
-                    let _ = std::process::Command::new("pkill")
-                        .args(["-f", "chromium --app=https://music.apple.com"])
-                        .spawn();
-                }
+/// Check whether our Apple Music instance is alive: its process exists and its MPRIS player is discoverable
+pub fn is_apple_music_running() -> bool {
+    let Ok(pid) = player::get_pid() else {
+        return false;
+    };
+
+    Path::new(&format!("/proc/{}", pid)).exists() && player::find_apple_music_player().is_ok()
+}
+
+/// Open a fresh Apple Music instance unless ours is still alive
+/// Returns whether a new instance was launched
+pub fn ensure_apple_music_open() -> Result<bool> {
+    if is_apple_music_running() {
+        info!("Apple Music is already running");
+        return Ok(false);
+    }
+
+    // Forget the dead instance before launching a new one
+    player::clear_pid();
+    open_apple_music()?;
+    Ok(true)
+}
+
+/// Whether a command line is one of a browser we launch Apple Music with
+fn is_app_command_line(args: &[&str], url: &str) -> bool {
+    let app_arg = format!("--app={}", url);
+    args.iter()
+        .any(|arg| *arg == app_arg || *arg == APP_CLASS_ARG)
+}
+
+/// Whether a process is a browser launched for Apple Music, rather than one of the user's own
+/// A music.apple.com tab in the user's browser must never be adopted, quitting would close it
+pub(crate) fn is_app_instance(pid: u32) -> bool {
+    let Ok(cmdline) = std::fs::read(format!("/proc/{}/cmdline", pid)) else {
+        return false;
+    };
+    let cmdline = String::from_utf8_lossy(&cmdline);
+    let args: Vec<&str> = cmdline.split('\0').collect();
+
+    is_app_command_line(&args, &config::get().apple_music_url)
+}
+
+/// Find the PID of an Apple Music instance that is already running
+pub fn find_running_pid() -> Option<u32> {
+    // The oldest matching process is the browser's main process, which owns the MPRIS name
+    let output = std::process::Command::new("pgrep")
+        .args([
+            "-o",
+            "-f",
+            "--",
+            &format!("--app={}", config::get().apple_music_url),
+        ])
+        .output()
+        .ok()?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()
+        .filter(|pid| is_app_instance(*pid))
+}
+
+/// Whether a process still exists
+fn is_alive(pid: Pid) -> bool {
+    signal::kill(pid, None).is_ok()
+}
+
+/// Signal the process group led by a process, or the process alone if it doesn't lead one
+fn signal_group(pid: Pid, sig: Signal) -> nix::Result<()> {
+    signal::killpg(pid, sig).or_else(|_| signal::kill(pid, sig))
+}
+
+/// Wait up to the terminate timeout for a process to exit, returning whether it did
+fn wait_for_exit(mut has_exited: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(TERMINATE_TIMEOUT_SECS);
+    while !has_exited() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    has_exited()
+}
+
+/// Terminate the browser we spawned and its renderers: SIGTERM to the process group, SIGKILL if it lingers
+fn terminate_process_group(mut child: Child) {
+    let pid = Pid::from_raw(child.id() as i32);
+    info!("Terminating Apple Music process group {}", pid);
+
+    if let Err(e) = signal_group(pid, Signal::SIGTERM) {
+        warn!("Failed to send SIGTERM to {}: {}", pid, e);
+    }
+
+    // Our own child stays a zombie until reaped, so ask the handle rather than the kernel
+    if !wait_for_exit(|| !matches!(child.try_wait(), Ok(None))) {
+        warn!(
+            "Apple Music did not exit within {}s, sending SIGKILL",
+            TERMINATE_TIMEOUT_SECS
+        );
+        if let Err(e) = signal_group(pid, Signal::SIGKILL) {
+            error!("Failed to send SIGKILL to {}: {}", pid, e);
+        }
+    }
+
+    // Renderers can outlive the main process, sweep whatever is left of the group
+    let _ = signal::killpg(pid, Signal::SIGKILL);
+
+    // Reap the child so it doesn't linger as a zombie
+    match child.wait() {
+        Ok(status) => info!("Apple Music process exited with {}", status),
+        Err(e) => error!("Failed to wait for Apple Music process: {}", e),
+    }
+}
+
+/// Ask an instance we adopted rather than spawned to exit
+/// Only the process itself is signalled, its group may hold processes that aren't ours
+fn terminate_adopted(pid: u32) {
+    if !is_app_instance(pid) {
+        warn!(
+            "Not terminating process {}, it isn't a browser launched for Apple Music",
+            pid
+        );
+        return;
+    }
+
+    let pid = Pid::from_raw(pid as i32);
+    info!("Terminating adopted Apple Music process {}", pid);
+
+    if let Err(e) = signal::kill(pid, Signal::SIGTERM) {
+        warn!("Failed to send SIGTERM to {}: {}", pid, e);
+        return;
+    }
+
+    if !wait_for_exit(|| !is_alive(pid)) {
+        warn!(
+            "Adopted Apple Music process {} did not exit within {}s",
+            pid, TERMINATE_TIMEOUT_SECS
+        );
+    }
+}
+
+/// Record a relaunch, unless the maximum number of relaunches within the window was reached
+fn allow_relaunch() -> bool {
+    let Ok(mut times) = RELAUNCH_TIMES.lock() else {
+        return false;
+    };
+
+    let now = Instant::now();
+    let window = Duration::from_secs(RELAUNCH_WINDOW_SECS);
+    while times
+        .front()
+        .is_some_and(|time| now.duration_since(*time) >= window)
+    {
+        times.pop_front();
+    }
+
+    if times.len() >= MAX_RELAUNCHES {
+        return false;
+    }
+    times.push_back(now);
+    true
+}
+
+/// Launch Apple Music again after its window was closed, when enabled in the config
+pub fn relaunch_after_close() {
+    if !config::get().relaunch_on_close
+        || QUITTING.load(Ordering::SeqCst)
+        || RESTARTING.load(Ordering::SeqCst)
+    {
+        return;
+    }
+
+    if !allow_relaunch() {
+        warn!(
+            "Apple Music was closed {} times within {}s, not relaunching it",
+            MAX_RELAUNCHES, RELAUNCH_WINDOW_SECS
+        );
+        return;
+    }
+
+    info!("Apple Music window was closed, relaunching it");
+    player::clear_pid();
+
+    // Launching waits for the browser, keep the event listener free
+    std::thread::spawn(|| {
+        // Reap the closed browser, keeping the handle if it is somehow still running
+        if let Some(mut child) = take_child() {
+            match child.try_wait() {
+                Ok(Some(status)) => debug!("Closed Apple Music process exited with {}", status),
+                _ => store_child(child),
             }
         }
-        Err(_) => {
-            // Fallback to pkill if we don't have a valid player
-            let _ = std::process::Command::new("pkill")
-                .args(["-f", "chromium --app=https://music.apple.com"])
-                .spawn();
+
+        if let Err(e) = open_apple_music() {
+            error!("Failed to relaunch Apple Music: {}", e);
         }
+    });
+}
+
+/// Kill Apple Music process
+pub fn kill_apple_music() {
+    QUITTING.store(true, Ordering::SeqCst);
+    terminate_apple_music();
+}
+
+/// Clear the presence and kill Apple Music before amusic exits
+pub fn shutdown() {
+    // Clear Discord presence before exiting
+    let _ = discord::clear_presence();
+
+    // Kill Apple Music process
+    kill_apple_music();
+}
+
+/// Kill Apple Music and open a fresh instance, e.g. when the web player got stuck
+pub fn restart_apple_music() -> Result<()> {
+    info!("Restarting Apple Music");
+    RESTARTING.store(true, Ordering::SeqCst);
+
+    // Nothing is playing until the new player shows up
+    if let Err(e) = discord::clear_presence() {
+        warn!("Failed to clear presence: {}", e);
+    }
+
+    // Waits for the old browser to exit before launching the new one
+    terminate_apple_music();
+    player::clear_pid();
+
+    // The event listener re-establishes the presence once the new MPRIS player appears
+    let result = open_apple_music();
+    RESTARTING.store(false, Ordering::SeqCst);
+    result
+}
+
+/// Terminate our Apple Music instance and wait for it to exit
+fn terminate_apple_music() {
+    // Only the browser we spawned is ours to kill with its whole process group
+    if let Some(child) = take_child() {
+        terminate_process_group(child);
+        return;
+    }
+
+    match player::get_pid().ok().or_else(find_running_pid) {
+        Some(pid) => terminate_adopted(pid),
+        None => debug!("No Apple Music process to kill"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://music.apple.com";
+
+    #[test]
+    fn app_instances_are_told_apart_from_the_users_browser() {
+        assert!(is_app_command_line(
+            &[
+                "chromium",
+                "--app=https://music.apple.com",
+                "--no-first-run"
+            ],
+            URL
+        ));
+        assert!(is_app_command_line(
+            &["firefox", "--new-window", "--class=AppleMusic", URL],
+            URL
+        ));
+
+        // The user's own browser, even with Apple Music open in a tab
+        assert!(!is_app_command_line(&["chromium", URL], URL));
+        assert!(!is_app_command_line(&["/usr/lib/firefox/firefox"], URL));
+        assert!(!is_app_command_line(
+            &["chromium", "--app=https://music.apple.com/us/browse"],
+            URL
+        ));
     }
 }
@@ -0,0 +1,187 @@
+use crate::config;
+use crate::error::Result;
+use mpris::{LoopStatus, Metadata, PlaybackStatus, Player, ProgressTick, ProgressTracker};
+use std::cell::RefCell;
+use std::time::Duration;
+
+/// Playback state read from a player in one go
+pub struct PlaybackSnapshot {
+    pub status: PlaybackStatus,
+    pub position: Duration,
+    pub metadata: Metadata,
+}
+
+/// The surface of an MPRIS player the presence is built from
+/// Implemented by `mpris::Player`, a fake can stand in for it without a session bus
+pub trait MediaPlayer {
+    /// Name of the player, e.g. "Chromium"
+    fn identity(&self) -> &str;
+
+    /// Status, position and metadata, read together
+    fn snapshot(&self) -> Result<PlaybackSnapshot>;
+
+    /// Current playback status
+    fn playback_status(&self) -> Result<PlaybackStatus>;
+
+    /// Current position in the track
+    fn position(&self) -> Result<Duration>;
+
+    /// Metadata of the current track
+    fn metadata(&self) -> Result<Metadata>;
+
+    /// Whether shuffle is on
+    fn shuffle(&self) -> Result<bool>;
+
+    /// Current repeat mode
+    fn loop_status(&self) -> Result<LoopStatus>;
+}
+
+impl MediaPlayer for Player {
+    fn identity(&self) -> &str {
+        Player::identity(self)
+    }
+
+    fn snapshot(&self) -> Result<PlaybackSnapshot> {
+        Ok(PlaybackSnapshot {
+            status: self.get_playback_status()?,
+            position: self.get_position()?,
+            metadata: self.get_metadata()?,
+        })
+    }
+
+    fn playback_status(&self) -> Result<PlaybackStatus> {
+        Ok(self.get_playback_status()?)
+    }
+
+    fn position(&self) -> Result<Duration> {
+        Ok(self.get_position()?)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(self.get_metadata()?)
+    }
+
+    fn shuffle(&self) -> Result<bool> {
+        Ok(self.get_shuffle()?)
+    }
+
+    fn loop_status(&self) -> Result<LoopStatus> {
+        Ok(self.get_loop_status()?)
+    }
+}
+
+/// MPRIS player followed by a long-lived progress tracker, kept by the event listener
+/// The tracker needs a connection of its own, it would consume the listener's events otherwise
+pub struct TrackedPlayer<'a> {
+    player: &'a Player,
+    tracker: RefCell<ProgressTracker<'a>>,
+}
+
+impl<'a> TrackedPlayer<'a> {
+    /// Start tracking a player, waiting up to `progress_tick_ms` for changes on each snapshot
+    pub fn new(player: &'a Player) -> Result<Self> {
+        let tracker = player.track_progress(config::get().progress_tick_ms)?;
+
+        Ok(TrackedPlayer {
+            player,
+            tracker: RefCell::new(tracker),
+        })
+    }
+}
+
+impl MediaPlayer for TrackedPlayer<'_> {
+    fn identity(&self) -> &str {
+        self.player.identity()
+    }
+
+    fn snapshot(&self) -> Result<PlaybackSnapshot> {
+        // The tracker only reloads what changed since the last tick and interpolates the position
+        let mut tracker = self.tracker.borrow_mut();
+        let ProgressTick { progress, .. } = tracker.tick();
+
+        Ok(PlaybackSnapshot {
+            status: progress.playback_status(),
+            position: progress.position(),
+            metadata: progress.metadata().clone(),
+        })
+    }
+
+    fn playback_status(&self) -> Result<PlaybackStatus> {
+        MediaPlayer::playback_status(self.player)
+    }
+
+    fn position(&self) -> Result<Duration> {
+        MediaPlayer::position(self.player)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        MediaPlayer::metadata(self.player)
+    }
+
+    fn shuffle(&self) -> Result<bool> {
+        MediaPlayer::shuffle(self.player)
+    }
+
+    fn loop_status(&self) -> Result<LoopStatus> {
+        MediaPlayer::loop_status(self.player)
+    }
+}
+
+/// Player with a scripted state, standing in for an MPRIS player in tests
+#[cfg(test)]
+pub struct FakePlayer {
+    pub status: PlaybackStatus,
+    pub position: Duration,
+    pub metadata: Metadata,
+    pub shuffle: bool,
+    pub loop_status: LoopStatus,
+}
+
+#[cfg(test)]
+impl FakePlayer {
+    /// A player playing the metadata at the given position, shuffle and repeat off
+    pub fn playing(metadata: Metadata, position: Duration) -> Self {
+        FakePlayer {
+            status: PlaybackStatus::Playing,
+            position,
+            metadata,
+            shuffle: false,
+            loop_status: LoopStatus::None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl MediaPlayer for FakePlayer {
+    fn identity(&self) -> &str {
+        "Fake Player"
+    }
+
+    fn snapshot(&self) -> Result<PlaybackSnapshot> {
+        Ok(PlaybackSnapshot {
+            status: self.status,
+            position: self.position,
+            metadata: self.metadata.clone(),
+        })
+    }
+
+    fn playback_status(&self) -> Result<PlaybackStatus> {
+        Ok(self.status)
+    }
+
+    fn position(&self) -> Result<Duration> {
+        Ok(self.position)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(self.metadata.clone())
+    }
+
+    fn shuffle(&self) -> Result<bool> {
+        Ok(self.shuffle)
+    }
+
+    fn loop_status(&self) -> Result<LoopStatus> {
+        Ok(self.loop_status)
+    }
+}
@@ -1,6 +1,11 @@
+pub mod history;
 pub mod launcher;
+pub mod media_player;
+pub mod nowplaying_file;
 pub mod player;
 
 // Re-export commonly used functions
-pub use launcher::{kill_apple_music, open_apple_music};
+pub use launcher::{
+    ensure_apple_music_open, kill_apple_music, open_apple_music, restart_apple_music, shutdown,
+};
 pub use player::{start_event_listener, update_discord_presence};
@@ -0,0 +1,70 @@
+use crate::config;
+use crate::error::{AppError, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+// Last content written, so unchanged tracks don't rewrite the file on every poll
+lazy_static::lazy_static! {
+    static ref LAST_CONTENT: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Render the now playing template, replacing the {title}, {artist} and {album} tokens
+fn render(template: &str, title: &str, artist: &str, album: Option<&str>) -> String {
+    template
+        .replace("{title}", title)
+        .replace("{artist}", artist)
+        .replace("{album}", album.unwrap_or(""))
+}
+
+/// Replace the file content atomically, so readers never see a partial write
+fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    std::fs::write(&tmp_path, content).map_err(|e| {
+        AppError::Application(format!("Failed to write {}: {}", tmp_path.display(), e))
+    })?;
+    std::fs::rename(&tmp_path, path).map_err(|e| {
+        AppError::Application(format!(
+            "Failed to move {} to {}: {}",
+            tmp_path.display(),
+            path.display(),
+            e
+        ))
+    })
+}
+
+/// Write the content to the configured file, unless it is already there
+fn update(content: String) {
+    let Some(path) = config::get().nowplaying_file.map(PathBuf::from) else {
+        return;
+    };
+
+    let Ok(mut last) = LAST_CONTENT.lock() else {
+        return;
+    };
+    if last.as_deref() == Some(content.as_str()) {
+        return;
+    }
+
+    match write_atomic(&path, &content) {
+        Ok(()) => {
+            debug!("Now playing file updated: {:?}", content);
+            *last = Some(content);
+        }
+        Err(e) => warn!("{}", e),
+    }
+}
+
+/// Write the current track to the now playing file, if one is configured
+pub fn write(title: &str, artist: &str, album: Option<&str>) {
+    let template = config::get().nowplaying_template;
+    update(render(&template, title, artist, album));
+}
+
+/// Empty the now playing file, e.g. on pause or stop
+pub fn clear() {
+    update(String::new());
+}
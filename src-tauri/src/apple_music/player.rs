@@ -1,10 +1,21 @@
+use crate::apple_music::history::{self, RecentTrack};
+use crate::apple_music::media_player::{MediaPlayer, PlaybackSnapshot, TrackedPlayer};
+use crate::apple_music::{launcher, nowplaying_file};
+use crate::config;
+use crate::config::constants::UNKNOWN_ARTIST;
 use crate::discord;
 use crate::error::{AppError, Result};
-use crate::utils::artwork;
-use mpris::{Event, PlaybackStatus, Player, PlayerFinder, ProgressTick};
-use std::sync::Mutex;
+use crate::scrobble::{self, ScrobbleTrack};
+use crate::ui;
+use crate::utils::artwork::{self, ArtworkProvider};
+use dbus::ffidisp::{BusType, Connection};
+use mpris::{Event, FindingError, LoopStatus, Metadata, PlaybackStatus, Player, PlayerFinder};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex, OnceLock};
 use std::thread;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, trace, warn};
 
 // Store our Apple Music process PID
 lazy_static::lazy_static! {
@@ -15,7 +26,7 @@ lazy_static::lazy_static! {
 pub fn store_pid(pid: u32) -> Result<()> {
     match APPLE_MUSIC_PID.lock() {
         Ok(mut pid_guard) => {
-            println!("Storing Apple Music PID {} in global variable", pid);
+            debug!("Storing Apple Music PID {} in global variable", pid);
             *pid_guard = Some(pid);
             Ok(())
         }
@@ -26,6 +37,34 @@ pub fn store_pid(pid: u32) -> Result<()> {
     }
 }
 
+/// Forget the stored Apple Music PID once its player is gone
+pub(crate) fn clear_pid() {
+    match APPLE_MUSIC_PID.lock() {
+        Ok(mut pid_guard) => {
+            debug!("Clearing stored Apple Music PID");
+            *pid_guard = None;
+        }
+        Err(e) => error!("Failed to lock PID mutex: {}", e),
+    }
+}
+
+/// Forget the stored Apple Music PID if it's still the given one, returning whether it was
+/// A restart may have stored the PID of the new instance before the old one is seen shutting down
+pub(crate) fn clear_pid_if(pid: u32) -> bool {
+    match APPLE_MUSIC_PID.lock() {
+        Ok(mut pid_guard) if *pid_guard == Some(pid) => {
+            debug!("Clearing stored Apple Music PID {}", pid);
+            *pid_guard = None;
+            true
+        }
+        Ok(_) => false,
+        Err(e) => {
+            error!("Failed to lock PID mutex: {}", e);
+            false
+        }
+    }
+}
+
 /// Get the stored Apple Music PID
 pub(crate) fn get_pid() -> Result<u32> {
     match APPLE_MUSIC_PID.lock() {
@@ -40,19 +79,220 @@ pub(crate) fn get_pid() -> Result<u32> {
     }
 }
 
+/// Window class Apple Music is launched with, lowercased as it shows up in bus names
+const APPLE_MUSIC_CLASS: &str = "applemusic";
+
+/// Lookup sent to the discovery thread: the PID to match and where to send its bus name
+type DiscoveryRequest = (u32, mpsc::Sender<Result<String>>);
+
+// Channel to the discovery thread, which keeps one D-Bus connection for every lookup
+static DISCOVERY: OnceLock<Mutex<mpsc::Sender<DiscoveryRequest>>> = OnceLock::new();
+
+// Bus name of the player owned by the stored PID, so it isn't searched for on every call
+lazy_static::lazy_static! {
+    static ref BUS_NAME: Mutex<Option<(u32, String)>> = Mutex::new(None);
+}
+
+/// Get the channel to the discovery thread, starting the thread on first use
+fn discovery_sender() -> Result<mpsc::Sender<DiscoveryRequest>> {
+    let sender = DISCOVERY.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<DiscoveryRequest>();
+
+        thread::spawn(move || {
+            debug!("Starting MPRIS player discovery thread");
+
+            // The finder isn't Send, so it lives on this thread and is only replaced when its connection dies
+            let mut finder: Option<PlayerFinder> = None;
+            for (apple_music_pid, reply) in receiver {
+                // Connect a new finder on first use or after the previous connection died
+                let current = match finder.take() {
+                    Some(current) => Ok(current),
+                    None => PlayerFinder::new().map_err(AppError::from),
+                };
+                let result = current.and_then(|current| {
+                    let result = find_bus_name_for_pid(&current, apple_music_pid);
+                    finder = Some(current);
+                    result
+                });
+
+                if matches!(
+                    result,
+                    Err(AppError::DBus(_)) | Err(AppError::Finding(FindingError::DBusError(_)))
+                ) {
+                    warn!(
+                        "D-Bus connection of the player finder failed, reconnecting on next lookup"
+                    );
+                    finder = None;
+                }
+
+                let _ = reply.send(result);
+            }
+        });
+
+        Mutex::new(sender)
+    });
+
+    sender
+        .lock()
+        .map(|sender| sender.clone())
+        .map_err(|e| AppError::Application(format!("Failed to lock discovery channel: {}", e)))
+}
+
+/// The bus name found earlier for a PID, if any
+fn cached_bus_name(apple_music_pid: u32) -> Option<String> {
+    let guard = BUS_NAME.lock().ok()?;
+    match guard.as_ref() {
+        Some((pid, bus_name)) if *pid == apple_music_pid => Some(bus_name.clone()),
+        _ => None,
+    }
+}
+
+/// Remember the bus name of a PID, or forget it with `None`
+fn cache_bus_name(entry: Option<(u32, String)>) {
+    if let Ok(mut guard) = BUS_NAME.lock() {
+        *guard = entry;
+    }
+}
+
+/// Connect to the player behind a bus name, bounding every D-Bus call by the timeout
+fn connect_player(bus_name: String, timeout: Duration) -> Result<Player> {
+    let connection = Connection::get_private(BusType::Session)
+        .map_err(|e| AppError::Mpris(format!("Error connecting to session bus: {}", e)))?;
+
+    Player::new(connection, bus_name, timeout.as_millis() as i32)
+        .map_err(|e| AppError::Mpris(format!("Error connecting to player: {}", e)))
+}
+
 /// Find the Apple Music player instance using MPRIS
 pub fn find_apple_music_player() -> Result<Player> {
     // Get our stored PID
     let apple_music_pid = get_pid()?;
+    let timeout = Duration::from_secs(config::get().finder_timeout_secs);
 
-    // Find all players and match by PID
-    let finder = PlayerFinder::new()
-        .map_err(|e| AppError::Mpris(format!("Error creating PlayerFinder: {}", e)))?;
+    // The player keeps its bus name for its whole lifetime, only search again once it's gone
+    if let Some(bus_name) = cached_bus_name(apple_music_pid) {
+        match connect_player(bus_name.clone(), timeout) {
+            Ok(player) if player.is_running() => return Ok(player),
+            _ => {
+                debug!("Cached player {} is gone, searching again", bus_name);
+                cache_bus_name(None);
+            }
+        }
+    }
+
+    // Run the discovery on its own thread so a slow session bus can't block the caller
+    // The Player itself isn't Send, so only its bus name comes back
+    let (reply, receiver) = mpsc::channel();
+    discovery_sender()?
+        .send((apple_music_pid, reply))
+        .map_err(|_| AppError::Mpris("player discovery thread stopped".into()))?;
+
+    let bus_name = receiver
+        .recv_timeout(timeout)
+        .map_err(|_| AppError::Mpris("finder timed out".into()))??;
+    cache_bus_name(Some((apple_music_pid, bus_name.clone())));
+
+    connect_player(bus_name, timeout)
+}
+
+/// Extract the process ID from an MPRIS bus name such as `org.mpris.MediaPlayer2.chromium.instance1234`
+pub(crate) fn pid_from_bus_name(bus_name: &str) -> Option<u32> {
+    bus_name
+        .rsplit('.')
+        .next()?
+        .trim_start_matches(|c: char| !c.is_ascii_digit())
+        .parse()
+        .ok()
+}
+
+/// Whether a bus name belongs to the process, comparing whole PIDs so PID 35 can't match "instance3507"
+fn bus_name_matches_pid(bus_name: &str, pid: u32) -> bool {
+    pid_from_bus_name(bus_name) == Some(pid)
+}
+
+/// Match the Apple Music web app by its identity, the window class we launch it with,
+/// or the page it plays from
+fn is_apple_music_player(player: &Player) -> bool {
+    player.identity().contains("Apple Music")
+        || player
+            .bus_name()
+            .to_ascii_lowercase()
+            .contains(APPLE_MUSIC_CLASS)
+        || player
+            .get_metadata()
+            .ok()
+            .and_then(|metadata| metadata.url().map(|url| url.contains("music.apple.com")))
+            .unwrap_or(false)
+}
+
+/// Look for any Apple Music player on the bus, returning the PID of its browser
+/// Only browsers launched for Apple Music are considered, never one of the user's own
+pub(crate) fn find_any_apple_music_pid() -> Result<Option<u32>> {
+    let finder = PlayerFinder::new()?;
 
-    let players = finder
-        .find_all()
-        .map_err(|e| AppError::Mpris(format!("Error finding players: {}", e)))?;
+    let players = finder.find_all()?;
 
+    for player in players {
+        if is_apple_music_player(&player) {
+            if let Some(pid) = pid_from_bus_name(player.bus_name()) {
+                // Apple Music playing in a tab of the user's browser isn't ours to adopt
+                if !launcher::is_app_instance(pid) {
+                    debug!(
+                        "Ignoring Apple Music player {}, its browser wasn't launched for Apple Music",
+                        player.bus_name()
+                    );
+                    continue;
+                }
+
+                info!(
+                    "Found Apple Music player {} with PID {}",
+                    player.bus_name(),
+                    pid
+                );
+                return Ok(Some(pid));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Periodically look for an Apple Music instance opened manually and attach to it
+pub fn start_player_rescan() {
+    thread::spawn(|| {
+        debug!("Starting Apple Music player rescan thread");
+
+        loop {
+            // Only scan while no player is attached
+            if get_pid().is_err() {
+                match find_any_apple_music_pid() {
+                    Ok(Some(pid)) => {
+                        // The event listener picks the player up once the PID is stored
+                        if let Err(e) = store_pid(pid) {
+                            error!("Failed to store PID: {}", e);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Apple Music player rescan failed: {}", e),
+                }
+            }
+
+            thread::sleep(Duration::from_secs(
+                config::get().player_rescan_interval_secs,
+            ));
+        }
+    });
+}
+
+/// Find the D-Bus name of the MPRIS player owned by the given PID
+fn find_bus_name_for_pid(finder: &PlayerFinder, apple_music_pid: u32) -> Result<String> {
+    // Find all players and match by PID
+    let players = finder.find_all()?;
+    select_bus_name(&players, apple_music_pid)
+}
+
+/// Pick the bus name of the player owned by the given PID among the players on the bus
+fn select_bus_name(players: &[Player], apple_music_pid: u32) -> Result<String> {
     // If no players, return error
     if players.is_empty() {
         return Err(AppError::Player(format!(
@@ -61,23 +301,32 @@ pub fn find_apple_music_player() -> Result<Player> {
         )));
     }
 
-    // Search exclusively by PID
-    let pid_str = apple_music_pid.to_string();
-
-    // Search directly by D-Bus name containing our PID
+    // Search by PID first
     for player in players {
         // Get the D-Bus name
         let bus_name = player.bus_name();
 
-        // Check if bus_name contains our PID
-        if bus_name.contains(&pid_str) {
-            println!(
+        // Check if the bus name ends with our PID
+        if bus_name_matches_pid(bus_name, apple_music_pid) {
+            trace!(
                 "Found AppleMusic instance with PID {}: {}",
-                apple_music_pid, bus_name
+                apple_music_pid,
+                bus_name
             );
 
-            // Return the player directly
-            return Ok(player);
+            return Ok(bus_name.to_string());
+        }
+    }
+
+    // Some Chromium builds don't embed the PID in the bus name, fall back to recognizing the app
+    if !config::get().strict_pid_matching {
+        if let Some(player) = players.iter().find(|player| is_apple_music_player(player)) {
+            debug!(
+                "No player with PID {}, matched Apple Music by name: {}",
+                apple_music_pid,
+                player.bus_name()
+            );
+            return Ok(player.bus_name().to_string());
         }
     }
 
@@ -88,39 +337,398 @@ pub fn find_apple_music_player() -> Result<Player> {
     )))
 }
 
+/// Get the volume of the Apple Music player, between 0.0 and 1.0
+pub fn get_volume() -> Result<f64> {
+    let player = find_apple_music_player()?;
+
+    player
+        .checked_get_volume()?
+        .ok_or_else(|| AppError::Mpris("Volume is unsupported by this player".into()))
+}
+
+/// Set the volume of the Apple Music player, clamped between 0.0 and 1.0
+pub fn set_volume(level: f64) -> Result<f64> {
+    let player = find_apple_music_player()?;
+    let level = level.clamp(0.0, 1.0);
+
+    let applied = player.checked_set_volume(level)?;
+    if !applied {
+        return Err(AppError::Mpris(
+            "Volume is unsupported by this player".into(),
+        ));
+    }
+
+    Ok(level)
+}
+
+/// Skip to the next track in the Apple Music player
+pub fn skip_next() -> Result<()> {
+    let player = find_apple_music_player()?;
+    player.next()?;
+    Ok(())
+}
+
+/// Go back to the previous track in the Apple Music player
+pub fn skip_previous() -> Result<()> {
+    let player = find_apple_music_player()?;
+    player.previous()?;
+    Ok(())
+}
+
+/// Toggle between playing and paused in the Apple Music player
+pub fn toggle_playback() -> Result<()> {
+    let player = find_apple_music_player()?;
+    player.play_pause()?;
+    Ok(())
+}
+
+/// Join every reported artist for display, falling back to the unknown artist placeholder
+fn display_artists(artists: Option<Vec<&str>>) -> String {
+    let artists: Vec<&str> = artists
+        .unwrap_or_default()
+        .into_iter()
+        .map(str::trim)
+        .filter(|artist| !artist.is_empty())
+        .collect();
+
+    if artists.is_empty() {
+        UNKNOWN_ARTIST.to_string()
+    } else {
+        artists.join(&config::get().artist_separator)
+    }
+}
+
+/// First reported artist, falling back to the unknown artist placeholder
+fn primary_artist(artists: Option<Vec<&str>>) -> String {
+    artists
+        .unwrap_or_default()
+        .into_iter()
+        .map(str::trim)
+        .find(|artist| !artist.is_empty())
+        .unwrap_or(UNKNOWN_ARTIST)
+        .to_string()
+}
+
+/// Snapshot of the current track, as printed by `--now-playing`
+#[derive(Clone, Debug, Serialize)]
+pub struct NowPlaying {
+    pub title: String,
+    pub artist: String,
+    pub album: Option<String>,
+    pub status: String,
+    pub position_secs: u64,
+    pub length_secs: Option<u64>,
+}
+
+/// Read the current track straight from the MPRIS player
+pub fn read_now_playing() -> Result<NowPlaying> {
+    let player = find_apple_music_player()?;
+
+    let metadata = player.get_metadata()?;
+    let status = player.get_playback_status()?;
+
+    Ok(NowPlaying {
+        title: metadata.title().unwrap_or("No title").to_string(),
+        artist: display_artists(metadata.artists()),
+        album: metadata.album_name().map(str::to_string),
+        status: format!("{:?}", status),
+        position_secs: player.get_position().unwrap_or_default().as_secs(),
+        length_secs: track_length_secs(metadata.length()),
+    })
+}
+
+/// Current track, as returned to the frontend
+#[derive(Clone, Debug, Serialize)]
+pub struct TrackInfo {
+    pub title: String,
+    pub artist: String,
+    pub album: Option<String>,
+    pub position_secs: u64,
+    pub length_secs: Option<u64>,
+    pub artwork_url: Option<String>,
+    pub is_playing: bool,
+}
+
+/// Get the current track from the song cache, querying the MPRIS player when nothing is cached
+pub fn current_track() -> Result<TrackInfo> {
+    if let Some(song) = get_current_song_info()? {
+        // The elapsed time is frozen while paused
+        let position = song
+            .paused_at
+            .unwrap_or_else(|| unix_now() - song.start_time);
+
+        return Ok(TrackInfo {
+            title: song.title,
+            artist: song.artist,
+            album: song.album,
+            position_secs: position.max(0) as u64,
+            length_secs: song
+                .end_time
+                .map(|end| (end - song.start_time).max(0) as u64),
+            artwork_url: song.artwork_url,
+            is_playing: song.paused_at.is_none(),
+        });
+    }
+
+    let player = find_apple_music_player()?;
+    let metadata = player.get_metadata()?;
+    let status = player.get_playback_status()?;
+
+    Ok(TrackInfo {
+        title: metadata.title().unwrap_or("No title").to_string(),
+        artist: display_artists(metadata.artists()),
+        album: metadata.album_name().map(str::to_string),
+        position_secs: player.get_position().unwrap_or_default().as_secs(),
+        length_secs: track_length_secs(metadata.length()),
+        // Discord can't load anything else, neither can most frontends
+        artwork_url: metadata
+            .art_url()
+            .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+            .map(str::to_string),
+        is_playing: status == PlaybackStatus::Playing,
+    })
+}
+
+/// An MPRIS player on the session bus, as listed for debugging
+#[derive(Clone, Debug, Serialize)]
+pub struct PlayerSummary {
+    pub identity: String,
+    pub bus_name: String,
+    /// Process ID parsed from the bus name, when it carries one
+    pub pid: Option<u32>,
+    pub playback_status: Option<String>,
+    /// Whether this is the Apple Music player the presence is read from
+    pub is_apple_music: bool,
+}
+
+/// List every MPRIS player D-Bus reports, without touching any of them
+pub fn list_players() -> Result<Vec<PlayerSummary>> {
+    let players = PlayerFinder::new()?.find_all()?;
+
+    // Match the player the same way the presence does, name fallback included
+    let followed = get_pid()
+        .ok()
+        .and_then(|pid| select_bus_name(&players, pid).ok());
+
+    Ok(players
+        .iter()
+        .map(|player| {
+            let bus_name = player.bus_name().to_string();
+            let pid = pid_from_bus_name(&bus_name);
+            PlayerSummary {
+                identity: player.identity().to_string(),
+                playback_status: player
+                    .get_playback_status()
+                    .ok()
+                    .map(|status| format!("{:?}", status)),
+                is_apple_music: followed.as_deref() == Some(bus_name.as_str()),
+                pid,
+                bus_name,
+            }
+        })
+        .collect())
+}
+
+/// Push the current track to the frontend, with the playback state the caller just saw
+fn emit_track_changed(is_playing: bool) {
+    match current_track() {
+        Ok(mut track) => {
+            track.is_playing = is_playing;
+            ui::emit_track_changed(&track);
+        }
+        Err(e) => trace!("No track to send to the frontend: {}", e),
+    }
+}
+
 // Structure to cache song information
 #[derive(Clone, Debug)]
 struct SongInfo {
+    // MPRIS track id, identifies the song when the player reports one
+    track_id: Option<String>,
     title: String,
     artist: String,
+    // Artist as reported by MPRIS, used to match the cache when the artist was backfilled
+    reported_artist: String,
+    // Not part of the cache match, an album change alone doesn't refresh the presence
+    album: Option<String>,
+    album_artist: Option<String>,
     start_time: i64,
     end_time: Option<i64>,
     artwork_url: Option<String>,
     apple_music_url: String,
+    // Elapsed seconds frozen when the song was shown as paused
+    paused_at: Option<i64>,
+    // Shuffle and repeat glyphs shown in the presence, empty when disabled
+    playback_modes: String,
+    // Shown as episode and show instead of title and artist
+    is_podcast: bool,
     last_updated: Instant,
 }
 
+impl SongInfo {
+    /// Whether the player's current track is this song
+    /// Compares track ids when both are known, otherwise the title and reported artist
+    fn is_same_track(&self, track_id: Option<&str>, title: &str, artist: &str) -> bool {
+        match (self.track_id.as_deref(), track_id) {
+            (Some(cached), Some(current)) => cached == current,
+            _ => self.title == title && self.reported_artist == artist,
+        }
+    }
+
+    /// Text of the song the presence templates can refer to
+    fn text(&self) -> discord::TrackText<'_> {
+        discord::TrackText {
+            title: &self.title,
+            artist: &self.artist,
+            album: self.album.as_deref(),
+            album_artist: self.album_artist.as_deref(),
+            playback_modes: &self.playback_modes,
+            is_podcast: self.is_podcast,
+        }
+    }
+}
+
+/// Shuffle and repeat glyphs of the player, when enabled in the config
+/// Players that don't expose these properties simply get no indicator
+fn playback_modes(player: &dyn MediaPlayer) -> String {
+    if !config::get().show_playback_modes {
+        return String::new();
+    }
+
+    let mut modes = Vec::new();
+    if player.shuffle().unwrap_or(false) {
+        modes.push("🔀");
+    }
+    match player.loop_status() {
+        Ok(LoopStatus::Playlist) => modes.push("🔁"),
+        Ok(LoopStatus::Track) => modes.push("🔂"),
+        Ok(LoopStatus::None) | Err(_) => {}
+    }
+    modes.join(" ")
+}
+
+/// Shortest length of an album-less track taken for a podcast episode, in seconds
+const PODCAST_MIN_LENGTH_SECS: u64 = 20 * 60;
+
+/// Check whether the metadata describes a podcast episode rather than a song
+/// Trusts the genre when the player reports one, otherwise only long tracks without
+/// any album count, so songs missing their album aren't misclassified
+fn is_podcast(metadata: &Metadata, length_secs: Option<u64>) -> bool {
+    if !config::get().detect_podcasts {
+        return false;
+    }
+
+    let genres = metadata
+        .get("xesam:genre")
+        .and_then(|genre| genre.as_str_array())
+        .unwrap_or_default();
+    if !genres.is_empty() {
+        return genres
+            .iter()
+            .any(|genre| genre.to_lowercase().contains("podcast"));
+    }
+
+    let has_album = metadata
+        .album_name()
+        .is_some_and(|album| !album.trim().is_empty());
+    !has_album && length_secs.is_some_and(|length| length >= PODCAST_MIN_LENGTH_SECS)
+}
+
+/// Track id MPRIS players report when nothing is loaded
+const NO_TRACK_ID: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// MPRIS track id of the metadata, unless it's missing or the "no track" placeholder
+fn track_id(metadata: &Metadata) -> Option<String> {
+    metadata
+        .track_id()
+        .map(|track_id| track_id.as_str().to_string())
+        .filter(|track_id| !track_id.is_empty() && track_id != NO_TRACK_ID)
+}
+
+/// Drift between the shown and the actual position tolerated before re-syncing, in seconds
+const PROGRESS_DRIFT_TOLERANCE_SECS: i64 = 2;
+
+/// Longest track length taken at face value, in seconds
+const MAX_TRACK_LENGTH_SECS: u64 = 24 * 60 * 60;
+
+/// Song length in whole seconds, from the `Duration` the mpris crate converts
+/// `mpris:length` microseconds to
+/// Zero and absurd lengths, like the huge value a negative length wraps to, count as unknown
+fn track_length_secs(length: Option<Duration>) -> Option<u64> {
+    length
+        .map(|length| length.as_secs())
+        .filter(|secs| (1..=MAX_TRACK_LENGTH_SECS).contains(secs))
+}
+
 // Global cache for song information
 lazy_static::lazy_static! {
     static ref CURRENT_SONG: Mutex<Option<SongInfo>> = Mutex::new(None);
 }
 
+// Counters reported by the diagnostics
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
 /// Get cached song info if available and still current
-fn get_cached_song_info(title: &str, artist: &str) -> Option<SongInfo> {
+fn get_cached_song_info(track_id: Option<&str>, title: &str, artist: &str) -> Option<SongInfo> {
     if let Ok(guard) = CURRENT_SONG.lock() {
         if let Some(song) = guard.as_ref() {
-            // Check if it's the same song and cache is still fresh (less than 30 seconds old)
-            if song.title == title
-                && song.artist == artist
-                && song.last_updated.elapsed() < Duration::from_secs(30)
+            // Check if it's the same song and cache is still fresh
+            if song.is_same_track(track_id, title, artist)
+                && song.last_updated.elapsed() < Duration::from_secs(config::get().cache_ttl_secs)
             {
+                CACHE_HITS.fetch_add(1, Ordering::Relaxed);
                 return Some(song.clone());
             }
         }
     }
+    CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
     None
 }
 
+/// Song cache hits and misses since startup
+pub fn cache_stats() -> (u64, u64) {
+    (
+        CACHE_HITS.load(Ordering::Relaxed),
+        CACHE_MISSES.load(Ordering::Relaxed),
+    )
+}
+
+/// Get the cached song info regardless of its age
+fn get_current_song_info() -> Result<Option<SongInfo>> {
+    match CURRENT_SONG.lock() {
+        Ok(guard) => Ok(guard.clone()),
+        Err(e) => Err(AppError::Application(format!(
+            "Failed to lock song cache mutex: {}",
+            e
+        ))),
+    }
+}
+
+/// Mutate the cached song in place, if there is one
+fn update_cached_song(update: impl FnOnce(&mut SongInfo)) -> Result<()> {
+    match CURRENT_SONG.lock() {
+        Ok(mut guard) => {
+            if let Some(song) = guard.as_mut() {
+                update(song);
+            }
+            Ok(())
+        }
+        Err(e) => Err(AppError::Application(format!(
+            "Failed to lock song cache mutex: {}",
+            e
+        ))),
+    }
+}
+
+/// Record a song length that showed up late, keeping the cached start time
+fn update_cached_end_time(end_time: Option<i64>) -> Result<()> {
+    update_cached_song(|song| {
+        song.end_time = end_time;
+        song.last_updated = Instant::now();
+    })
+}
+
 /// Cache song information for later use
 fn cache_song_info(song_info: SongInfo) -> Result<()> {
     match CURRENT_SONG.lock() {
@@ -135,98 +743,249 @@ fn cache_song_info(song_info: SongInfo) -> Result<()> {
     }
 }
 
+/// Check the media against the URL allowlist and title blocklist
+fn is_allowed_media(url: Option<&str>, title: &str) -> bool {
+    // Only page URLs can be checked, media blobs and missing URLs are let through
+    let config = config::get();
+    if let Some(url) = url.filter(|url| url.starts_with("http://") || url.starts_with("https://")) {
+        let allowlist = &config.url_allowlist;
+        if !allowlist.is_empty()
+            && !allowlist
+                .iter()
+                .any(|allowed| url.contains(allowed.as_str()))
+        {
+            debug!("Ignoring media from non allowlisted URL: {}", url);
+            return false;
+        }
+    }
+
+    let title = title.to_lowercase();
+    if let Some(blocked) = config
+        .title_blocklist
+        .iter()
+        .find(|blocked| title.contains(&blocked.to_lowercase()))
+    {
+        debug!("Ignoring media with blocklisted title: {}", blocked);
+        return false;
+    }
+
+    true
+}
+
+/// Check whether the metadata looks like an ad or interstitial rather than a song
+/// Kept conservative: real tracks always have a title, and short ones still have an artist
+fn is_interstitial(has_title: bool, title: &str, has_artist: bool, length_secs: i64) -> bool {
+    let untitled = !has_title || title.trim().eq_ignore_ascii_case("No title");
+
+    // Promotions either come without a title, or without an artist nor a length
+    (untitled && has_artist) || (has_title && !has_artist && length_secs <= 0)
+}
+
+/// Current UNIX time in seconds
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Show the current track outside of Discord: in the now playing file and the tray tooltip
+fn publish_now_playing(title: &str, artist: &str, album: Option<&str>) {
+    nowplaying_file::write(title, artist, album);
+    ui::set_tray_tooltip(Some((title, artist)));
+}
+
+/// Reset the now playing file and the tray tooltip once playback stops
+fn clear_now_playing() {
+    nowplaying_file::clear();
+    ui::set_tray_tooltip(None);
+}
+
 /// Function to update Discord presence based on current player state
 /// This function ensures we get complete song data before updating Discord
 /// and once a song's duration is determined, it remains consistent
 /// even if the user skips around in the track using the progress bar
 pub fn update_discord_presence() -> Result<String> {
+    // The user paused the presence from the tray, make sure nothing lingers
+    if !discord::is_presence_enabled() {
+        discord::clear_presence()?;
+        return Ok("Discord presence paused".to_string());
+    }
+
     // Find our specific Apple Music player
-    println!("Updating Discord presence - looking for our Apple Music player...");
+    trace!("Updating Discord presence - looking for our Apple Music player...");
     let player = match find_apple_music_player() {
         Ok(p) => p,
         Err(e) => {
             // If we don't find our specific player, return the error
-            println!("Could not find our specific Apple Music player: {}", e);
+            trace!("Could not find our specific Apple Music player: {}", e);
             return Err(e);
         }
     };
 
-    println!("Found player: {}", player.identity());
+    trace!("Found player: {}", player.identity());
 
-    // Create a progress tracker to get accurate position information
-    let mut progress_tracker = player
-        .track_progress(100) // Update every 100ms
-        .map_err(|e| AppError::Mpris(format!("Error creating progress tracker: {}", e)))?;
+    // Read the player state with accurate position information
+    // If the player vanished since discovery (e.g. after a page reload), rediscover it once
+    match player.snapshot() {
+        Ok(snapshot) => {
+            let providers = artwork::configured_chain(snapshot.metadata.art_url());
+            update_presence_from(&player, snapshot, &providers)
+        }
+        Err(e) => {
+            warn!(
+                "Error reading player state: {}. Rediscovering Apple Music player...",
+                e
+            );
+            let player = find_apple_music_player()?;
+            debug!("Rediscovered player: {}", player.identity());
 
-    // Get current progress with accurate timing information
-    let ProgressTick { progress, .. } = progress_tracker.tick();
+            let snapshot = player.snapshot().map_err(|e| {
+                AppError::Mpris(format!(
+                    "Error reading player state after rediscovery: {}",
+                    e
+                ))
+            })?;
+            let providers = artwork::configured_chain(snapshot.metadata.art_url());
+            update_presence_from(&player, snapshot, &providers)
+        }
+    }
+}
+
+/// Update the presence from the event listener's tracked player
+/// Falls back to looking the player up again when the tracker can't be read
+fn update_tracked_presence(tracked: &TrackedPlayer) -> Result<String> {
+    if !discord::is_presence_enabled() {
+        return update_discord_presence();
+    }
 
+    match tracked.snapshot() {
+        Ok(snapshot) => {
+            let providers = artwork::configured_chain(snapshot.metadata.art_url());
+            update_presence_from(tracked, snapshot, &providers)
+        }
+        Err(e) => {
+            debug!("Could not read the tracked player: {}", e);
+            update_discord_presence()
+        }
+    }
+}
+
+/// Build and send the presence from a snapshot of the player state,
+/// searching the artwork of regular tracks with the given providers
+fn update_presence_from(
+    player: &dyn MediaPlayer,
+    snapshot: PlaybackSnapshot,
+    providers: &dyn ArtworkProvider,
+) -> Result<String> {
     // Check if player is actually playing something
-    if progress.playback_status() != PlaybackStatus::Playing {
+    if snapshot.status != PlaybackStatus::Playing {
         // We only clear the presence if the player is explicitly paused or stopped
         // We handle this through the Event::Paused or Event::Stopped events
         // We don't clear here to avoid flashing during song changes
         return Err(AppError::Player("Player is not currently playing".into()));
     }
 
-    let metadata = progress.metadata();
+    let metadata = &snapshot.metadata;
+    let track_id = track_id(metadata);
     let title = metadata.title().unwrap_or("No title").to_string();
-    let artist = metadata.artists().unwrap_or(vec!["Unknown"])[0].to_string();
+    let artist = display_artists(metadata.artists());
+    let primary_artist = primary_artist(metadata.artists());
+    let mpris_art_url = metadata.art_url().map(str::to_string);
+
+    // Make sure unrelated media sessions don't leak into the presence
+    if !is_allowed_media(metadata.url(), &title) {
+        return Err(AppError::Player(
+            "Media is not allowed to update the presence".into(),
+        ));
+    }
+
+    // Without any metadata at all, show a tidy placeholder instead of "No title" by "Unknown"
+    let has_title = metadata
+        .title()
+        .is_some_and(|title| !title.trim().is_empty());
+    let has_artist = metadata
+        .artists()
+        .is_some_and(|artists| artists.iter().any(|artist| !artist.trim().is_empty()));
+    if !has_title && !has_artist {
+        discord::set_placeholder_activity(
+            &config::get().unknown_track_template,
+            unix_now() - snapshot.position.as_secs() as i64,
+        )?;
+        return Ok("Discord presence active: no track metadata available".to_string());
+    }
+
+    // Leave the previous activity up while an ad or interstitial plays
+    let length_secs = track_length_secs(metadata.length()).map_or(0, |secs| secs as i64);
+    if is_interstitial(has_title, &title, has_artist, length_secs) {
+        debug!("Skipping presence update for interstitial: {:?}", title);
+        return Err(AppError::Player(
+            "Media looks like an ad or interstitial".into(),
+        ));
+    }
+
+    let album = metadata.album_name().map(str::to_string);
+    let album_artist = metadata
+        .album_artists()
+        .and_then(|artists| artists.first().map(|artist| artist.to_string()));
 
     // Get song duration and position from progress
-    let position = progress.position().as_secs() as i64;
-    let length = progress
-        .length()
-        .map(|duration| duration.as_secs() as i64)
-        .unwrap_or(0);
+    let position = snapshot.position.as_secs() as i64;
+    let length = track_length_secs(metadata.length());
+    let podcast = is_podcast(metadata, length);
 
     // Calculate when the song started playing
-    let current_time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64;
-    let start_time = current_time - position;
-
-    // Determine end_time based on song length
-    let end_time = if length > 0 && length < 86400 {
-        // Less than 24 hours
-        // Only set end_time if we have a reasonable length
-        Some(start_time + length)
-    } else {
-        None
-    };
+    let start_time = unix_now() - position;
+
+    // Only set end_time if we have a reasonable length
+    let end_time = length.map(|length| start_time + length as i64);
 
     // Check if we have cached info for this song
-    if let Some(mut cached_song) = get_cached_song_info(&title, &artist) {
-        println!("Using cached song information for {} - {}", artist, title);
+    if let Some(mut cached_song) = get_cached_song_info(track_id.as_deref(), &title, &artist) {
+        trace!("Using cached song information for {} - {}", artist, title);
 
         // Only update end_time if we don't have one yet but now we do
         if cached_song.end_time.is_none() && end_time.is_some() {
-            println!(
+            debug!(
                 "Updating end time with newly available information: {:?}",
                 end_time
             );
+            // Keep the original start_time from cache to maintain consistency
             cached_song.end_time = end_time;
+            let _ = update_cached_end_time(end_time);
+        }
 
-            // Update the cache with the new end_time
-            let updated_song = SongInfo {
-                title: cached_song.title.clone(),
-                artist: cached_song.artist.clone(),
-                // Keep the original start_time from cache to maintain consistency
-                start_time: cached_song.start_time,
-                end_time,
-                artwork_url: cached_song.artwork_url.clone(),
-                apple_music_url: cached_song.apple_music_url.clone(),
-                last_updated: Instant::now(),
+        // Resuming from a frozen paused presence, restart the timestamps at the current position
+        if cached_song.paused_at.is_some() {
+            debug!("Resuming from pause, recomputing timestamps");
+            let resume = |song: &mut SongInfo| {
+                song.start_time = start_time;
+                song.end_time = end_time;
+                song.paused_at = None;
+                song.last_updated = Instant::now();
             };
-            let _ = cache_song_info(updated_song);
+            resume(&mut cached_song);
+            let _ = update_cached_song(resume);
         }
 
+        // Shuffle and repeat may have been toggled since the song was cached
+        let modes = playback_modes(player);
+        if cached_song.playback_modes != modes {
+            cached_song.playback_modes = modes.clone();
+            let _ = update_cached_song(|song| song.playback_modes = modes);
+        }
+
+        scrobble::track_progress(position as u64);
+        publish_now_playing(
+            &cached_song.title,
+            &cached_song.artist,
+            cached_song.album.as_deref(),
+        );
+
         // Always use the cached start_time and end_time values
         // This ensures consistency even if the user moves the progress bar
         discord::set_activity(
-            &cached_song.title,
-            &cached_song.artist,
+            &cached_song.text(),
             cached_song.artwork_url.as_deref(),
             cached_song.start_time,
             cached_song.end_time,
@@ -235,32 +994,124 @@ pub fn update_discord_presence() -> Result<String> {
 
         return Ok(format!(
             "Discord presence active (cached): {} - {}",
-            artist, title
+            cached_song.artist, title
         ));
     }
 
-    // Try to find album cover online using iTunes API
-    let artwork_url = artwork::get_artwork_url(&artist, &title);
+    // Keep the artist reported by MPRIS so the cache can match it later
+    let reported_artist = artist.clone();
 
-    // Create search URL for Apple Music
-    let apple_music_url = artwork::get_apple_music_search_url(&title, &artist);
+    // Try to find album cover using the configured artwork providers
+    let mpris_artwork = artwork::mpris_artwork(mpris_art_url.as_deref());
+    let (artwork_url, artist) = if podcast {
+        // Episode art comes with the metadata, searching iTunes for songs would only find noise
+        debug!("Podcast detected, using the MPRIS artwork: {}", title);
+        (
+            artwork::MprisProvider {
+                art_url: mpris_art_url.clone(),
+            }
+            .artwork_url(&artist, &title, None),
+            artist,
+        )
+    } else if artwork::is_unknown_artist(&artist) {
+        // Without an artist, search by title only and backfill the artist from the result
+        debug!(
+            "Artist unknown for {}, searching iTunes by title only",
+            title
+        );
+        let lookup = artwork::lookup_by_title(&title);
+        (
+            mpris_artwork.or(lookup.artwork_url),
+            lookup.artist.unwrap_or(artist),
+        )
+    } else if let Some(album) = album
+        .as_deref()
+        .filter(|_| artwork::is_compilation(&artist, album_artist.as_deref()))
+    {
+        // Compilations rarely match by track artist, so look the album up instead
+        debug!("Compilation detected, searching iTunes by album: {}", album);
+        let artwork_url = mpris_artwork
+            .or_else(|| artwork::get_album_artwork_url(album))
+            .or_else(|| providers.artwork_url(&primary_artist, &title, Some(album)));
+        (artwork_url, artist)
+    } else {
+        // Featured artists over-constrain the search, the primary artist is enough
+        (
+            providers.artwork_url(&primary_artist, &title, album.as_deref()),
+            artist,
+        )
+    };
+
+    // Link the button to song.link when enabled, or to an Apple Music search
+    let apple_music_url = if podcast {
+        artwork::get_apple_music_search_url(&title, &artist)
+    } else {
+        artwork::get_play_url(&title, &artist)
+    };
 
     // Cache this song information
     let song_info = SongInfo {
+        track_id,
         title: title.clone(),
         artist: artist.clone(),
+        reported_artist,
+        album: album.clone(),
+        album_artist: album_artist.clone(),
         start_time,
         end_time,
         artwork_url: artwork_url.clone(),
         apple_music_url: apple_music_url.clone(),
+        paused_at: None,
+        playback_modes: playback_modes(player),
+        is_podcast: podcast,
         last_updated: Instant::now(),
     };
+    let modes = song_info.playback_modes.clone();
     cache_song_info(song_info)?;
 
-    // Update Discord activity
-    discord::set_activity(
+    // Remember the track and refresh the tray's recent tracks submenu
+    if history::push(RecentTrack::new(
         &title,
         &artist,
+        album.as_deref(),
+        &apple_music_url,
+    )) {
+        ui::refresh_tray_menu();
+        emit_track_changed(true);
+        ui::notify_track(&title, &artist, artwork_url.as_deref());
+    }
+
+    // Every new listen is announced, even of the track the history already ends with
+    // Scrobbling services know the primary artist best, and never an unknown one
+    // Podcast episodes aren't listens of music, so they aren't scrobbled
+    let scrobble_artist = if artwork::is_unknown_artist(&primary_artist) {
+        &artist
+    } else {
+        &primary_artist
+    };
+    if !podcast && !artwork::is_unknown_artist(scrobble_artist) {
+        scrobble::track_started(ScrobbleTrack {
+            title: title.clone(),
+            artist: scrobble_artist.clone(),
+            album: album.clone(),
+            length_secs: end_time.map(|end| (end - start_time) as u64),
+            started_at: start_time,
+        });
+    }
+    scrobble::track_progress(position as u64);
+    publish_now_playing(&title, &artist, album.as_deref());
+
+    // Update Discord activity
+    let track = discord::TrackText {
+        title: &title,
+        artist: &artist,
+        album: album.as_deref(),
+        album_artist: album_artist.as_deref(),
+        playback_modes: &modes,
+        is_podcast: podcast,
+    };
+    discord::set_activity(
+        &track,
         artwork_url.as_deref(),
         start_time,
         end_time,
@@ -270,87 +1121,777 @@ pub fn update_discord_presence() -> Result<String> {
     Ok(format!("Discord presence active: {} - {}", artist, title))
 }
 
+// Incremented on every playback change so that pending idle clears can detect they are stale
+static IDLE_CLEAR_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Cancel any pending idle clear
+fn cancel_idle_clear() {
+    IDLE_CLEAR_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Clear the Discord presence after a short delay, unless playback resumes first
+fn schedule_idle_clear() {
+    let generation = IDLE_CLEAR_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+
+    let delay_ms = config::get().idle_clear_delay_ms;
+
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(delay_ms));
+
+        // Playback resumed or another pause arrived in the meantime
+        if IDLE_CLEAR_GENERATION.load(Ordering::SeqCst) != generation {
+            debug!("Idle clear cancelled, playback changed");
+            return;
+        }
+
+        debug!("Player idle for {}ms, clearing presence", delay_ms);
+        if let Err(e) = discord::clear_presence() {
+            error!("Failed to clear presence: {}", e);
+        }
+    });
+}
+
+// Incremented on every track change so that only the last change of a burst updates the presence
+static TRACK_CHANGE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Update the presence once track changes settle, collapsing quick skips into a single update
+fn schedule_track_update() {
+    let generation = TRACK_CHANGE_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+
+    let delay_ms = config::get().track_change_debounce_ms;
+
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(delay_ms));
+
+        // Another track change arrived in the meantime, it will do the update
+        if TRACK_CHANGE_GENERATION.load(Ordering::SeqCst) != generation {
+            trace!("Track update superseded by a newer track change");
+            return;
+        }
+
+        // A successful update means we're playing again
+        if update_discord_presence().is_ok() {
+            cancel_idle_clear();
+        }
+    });
+}
+
+/// Re-push the cached activity with timestamps refreshed from the player's position
+pub fn reassert_presence() -> Result<()> {
+    let player = find_apple_music_player()?;
+
+    let status = player.get_playback_status()?;
+    if status != PlaybackStatus::Playing {
+        return Err(AppError::Player("Player is not currently playing".into()));
+    }
+
+    let Some(song) = get_current_song_info()? else {
+        return Err(AppError::Player("No song to re-assert".into()));
+    };
+    if song.paused_at.is_some() {
+        return Err(AppError::Player("Song is shown as paused".into()));
+    }
+
+    // Shift the timestamps to the current position, keeping the song length
+    let position = player.get_position()?.as_secs() as i64;
+    let length = song.end_time.map(|end| end - song.start_time);
+    resync_song(song, position, length)
+}
+
+/// Move the song's timestamps to the given position, then push and cache them
+fn resync_song(mut song: SongInfo, position: i64, length: Option<i64>) -> Result<()> {
+    song.start_time = unix_now() - position;
+    song.end_time = length.map(|length| song.start_time + length);
+    song.last_updated = Instant::now();
+
+    discord::set_activity(
+        &song.text(),
+        song.artwork_url.as_deref(),
+        song.start_time,
+        song.end_time,
+        &song.apple_music_url,
+    )?;
+
+    cache_song_info(song)
+}
+
+/// Jump the progress bar to the position the user seeked to
+fn resync_after_seek(player: &dyn MediaPlayer, position_in_us: u64) -> Result<()> {
+    let Some(song) = get_current_song_info()? else {
+        return Err(AppError::Player("No song to resync".into()));
+    };
+
+    // A paused presence shows the frozen elapsed time instead
+    if song.paused_at.is_some() {
+        return show_paused_presence(player);
+    }
+
+    let position = (position_in_us / 1_000_000) as i64;
+    let length = song.end_time.map(|end| end - song.start_time);
+    resync_song(song, position, length)
+}
+
+/// Lightweight progress check run between MPRIS events
+/// Only reads the position and length, and re-syncs the timestamps when they drifted
+/// or the song length showed up late
+/// Returns whether the song length is still unknown, so the caller can check back sooner
+pub fn refresh_progress() -> Result<bool> {
+    let Some(song) = get_current_song_info()? else {
+        return Ok(false);
+    };
+    if song.paused_at.is_some() {
+        return Ok(false);
+    }
+
+    let player = find_apple_music_player()?;
+    if player.get_playback_status()? != PlaybackStatus::Playing {
+        return Ok(false);
+    }
+
+    // A track change whose event got lost needs a full update, not a re-sync
+    let metadata = player.get_metadata()?;
+    let title = metadata.title().unwrap_or("No title");
+    let artist = display_artists(metadata.artists());
+    if !song.is_same_track(track_id(&metadata).as_deref(), title, &artist) {
+        debug!("Track changed without an event, updating presence");
+        update_discord_presence()?;
+        return Ok(false);
+    }
+
+    let position = player.get_position()?.as_secs() as i64;
+    scrobble::track_progress(position as u64);
+
+    // Keep a known length, otherwise check whether the player reports it by now
+    let length = match song.end_time {
+        Some(end_time) => Some(end_time - song.start_time),
+        None => track_length_secs(metadata.length()).map(|secs| secs as i64),
+    };
+    let length_found = song.end_time.is_none() && length.is_some();
+
+    let drift = (unix_now() - song.start_time - position).abs();
+    if drift <= PROGRESS_DRIFT_TOLERANCE_SECS && !length_found {
+        return Ok(length.is_none());
+    }
+
+    debug!(
+        "Re-syncing progress for {} - {} (drift {}s)",
+        song.artist, song.title, drift
+    );
+    resync_song(song, position, length)?;
+    Ok(length.is_none())
+}
+
+/// Show the current song as paused, freezing the elapsed time at the player's position
+fn show_paused_presence(player: &dyn MediaPlayer) -> Result<()> {
+    if !discord::is_presence_enabled() {
+        return Err(AppError::Player("Discord presence is paused".into()));
+    }
+
+    let Some(mut song) = get_current_song_info()? else {
+        return Err(AppError::Player("No song to show as paused".into()));
+    };
+
+    // Prefer the player's position, fall back to the time since the song started
+    let elapsed = match player.position() {
+        Ok(position) => position.as_secs() as i64,
+        Err(_) => (unix_now() - song.start_time).max(0),
+    };
+
+    discord::set_paused_activity(
+        &song.text(),
+        song.artwork_url.as_deref(),
+        elapsed,
+        &song.apple_music_url,
+    )?;
+
+    song.paused_at = Some(elapsed);
+    song.last_updated = Instant::now();
+    cache_song_info(song)
+}
+
 /// Function to listen for MPRIS events and update Discord presence accordingly
+/// Returns once the player is gone, or an error when it could not be found
 pub fn listen_for_player_events() -> Result<()> {
     // Try to find our specific player
-    println!("Attempting to find Apple Music player for event listening...");
+    trace!("Attempting to find Apple Music player for event listening...");
+    let listened_pid = get_pid()?;
     let player = match find_apple_music_player() {
         Ok(p) => p,
         Err(e) => {
             // If we can't find the player, wait a bit and return the error
             // so that the main loop tries again
-            println!(
+            debug!(
                 "Could not find Apple Music player: {}. Waiting before retry...",
                 e
             );
-            std::thread::sleep(Duration::from_secs(5));
             return Err(e);
         }
     };
 
     // Watch for events from this player
     let player_name = player.identity().to_string();
-    println!("Monitoring player: {}", player_name);
+    info!("Monitoring player: {}", player_name);
+
+    // One progress tracker for as long as the player is listened to, on a connection of its own
+    let tracked_player = connect_player(
+        player.bus_name().to_string(),
+        Duration::from_secs(config::get().finder_timeout_secs),
+    )?;
+    let tracked = TrackedPlayer::new(&tracked_player)?;
 
-    // Get player events stream
-    println!("Setting up event listener for player: {}", player_name);
-    let events = player
-        .events()
-        .map_err(|e| AppError::Mpris(format!("Error getting player events: {}", e)))?;
+    // No Playing event fires for a player that is already playing when we attach
+    if update_tracked_presence(&tracked).is_ok() {
+        cancel_idle_clear();
+    }
 
-    println!("Successfully connected to player events stream");
+    loop {
+        // Get player events stream
+        debug!("Setting up event listener for player: {}", player_name);
+        let events = player.events()?;
 
-    for event_result in events {
-        println!("Received event from player: {:?}", event_result);
+        debug!("Successfully connected to player events stream");
 
-        if let Ok(event) = event_result {
-            match event {
-                Event::Playing => {
-                    println!("Event: Player started playing");
-                    let _ = update_discord_presence();
-                }
-                Event::Paused | Event::Stopped => {
-                    println!("Event: Player paused or stoped");
-                }
-                Event::TrackChanged(_) | Event::Seeked { position_in_us: _ } => {
-                    println!("Event: Track changed");
-                    let _ = update_discord_presence();
-                }
-                Event::PlayerShutDown => {
-                    println!("Event: Player shut down");
-                    let _ = discord::clear_presence();
-                    return Ok(());
-                }
-                _ => {
-                    println!("Unhandled event: {:?}", event);
+        for event_result in events {
+            trace!("Received event from player: {:?}", event_result);
+
+            if let Ok(event) = event_result {
+                match event {
+                    Event::Playing => {
+                        debug!("Event: Player started playing");
+                        cancel_idle_clear();
+                        let _ = update_tracked_presence(&tracked);
+                        emit_track_changed(true);
+                    }
+                    Event::Paused if !config::get().clear_on_pause => {
+                        debug!("Event: Player paused");
+                        clear_now_playing();
+                        cancel_idle_clear();
+                        if let Err(e) = show_paused_presence(&player) {
+                            warn!("Could not show paused presence: {}", e);
+                            schedule_idle_clear();
+                        }
+                        emit_track_changed(false);
+                    }
+                    Event::Paused | Event::Stopped => {
+                        debug!("Event: Player paused or stoped");
+                        clear_now_playing();
+                        schedule_idle_clear();
+                        emit_track_changed(false);
+                    }
+                    Event::Seeked { position_in_us } => {
+                        debug!("Event: Seeked to {}us", position_in_us);
+                        // Without a cached song yet, fall back to a full update
+                        if let Err(e) = resync_after_seek(&player, position_in_us) {
+                            debug!("Could not resync after seek: {}", e);
+                            if update_tracked_presence(&tracked).is_ok() {
+                                cancel_idle_clear();
+                            }
+                        }
+                    }
+                    Event::ShuffleToggled(_) | Event::LoopingChanged(_) => {
+                        debug!("Event: Shuffle or repeat changed");
+                        if config::get().show_playback_modes {
+                            let _ = update_tracked_presence(&tracked);
+                        }
+                    }
+                    Event::TrackChanged(_) => {
+                        debug!("Event: Track changed");
+                        schedule_track_update();
+                    }
+                    Event::PlayerShutDown => {
+                        info!("Event: Player shut down");
+                        // Let the rescan thread attach to an instance reopened manually,
+                        // leaving alone an instance that replaced this one meanwhile
+                        if clear_pid_if(listened_pid) {
+                            clear_now_playing();
+                            let _ = discord::clear_presence();
+                            launcher::relaunch_after_close();
+                        }
+                        return Ok(());
+                    }
+                    _ => {
+                        debug!("Unhandled event: {:?}", event);
+                    }
                 }
+            } else if let Err(e) = event_result {
+                warn!("Error handling player event: {:?}", e);
             }
-        } else if let Err(e) = event_result {
-            println!("Error handling player event: {:?}", e);
         }
-    }
 
-    Err(AppError::Player(
-        "Player events stream ended unexpectedly".into(),
-    ))
+        // The stream ended: check whether the player is really gone
+        if !player.is_running() {
+            info!("Player events stream ended and the player is gone, clearing presence");
+            if clear_pid_if(listened_pid) {
+                let _ = discord::clear_presence();
+                clear_now_playing();
+                launcher::relaunch_after_close();
+            }
+            return Ok(());
+        }
+
+        // The player is still there, so reconnect to it right away
+        warn!("Player events stream ended unexpectedly, reconnecting to the same player");
+        thread::sleep(Duration::from_millis(500));
+    }
 }
 
+/// Delay before looking for the player again, right after launch or after it went away, in seconds
+const PLAYER_FIND_MIN_DELAY_SECS: u64 = 1;
+
+/// Longest delay between attempts to find the player, in seconds
+const PLAYER_FIND_MAX_DELAY_SECS: u64 = 30;
+
 /// Start the event listener thread for MPRIS events
 pub fn start_event_listener() {
     thread::spawn(|| {
-        // Wait a bit before starting to listen for events
-        thread::sleep(Duration::from_secs(3));
-        println!("Starting MPRIS event listener thread");
+        debug!("Starting MPRIS event listener thread");
+
+        let min_delay = Duration::from_secs(PLAYER_FIND_MIN_DELAY_SECS);
+        let max_delay = Duration::from_secs(PLAYER_FIND_MAX_DELAY_SECS);
+        let mut delay = min_delay;
 
         loop {
-            if let Err(e) = listen_for_player_events() {
-                // Only print errors that aren't due to unstored PID
-                if !e.to_string().contains("PID not stored") {
-                    eprintln!("Error in player events listener: {}", e);
+            match listen_for_player_events() {
+                // The monitored player went away, look for the next one promptly
+                Ok(()) => delay = min_delay,
+                Err(e) => {
+                    // Only print errors that aren't due to unstored PID
+                    if !e.to_string().contains("PID not stored") {
+                        error!("Error in player events listener: {}", e);
+                    }
                 }
-                // Wait a bit before trying again
-                thread::sleep(Duration::from_secs(3));
             }
+
+            // Back off while no player shows up, to go easy on D-Bus during long idle periods
+            thread::sleep(delay);
+            delay = (delay * 2).min(max_delay);
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apple_music::media_player::FakePlayer;
+    use crate::config::Config;
+    use crate::discord::sink::RecordingSink;
+    use crate::testing;
+    use mpris::MetadataValue;
+    use std::collections::HashMap;
+
+    /// Metadata with the given MPRIS fields
+    fn metadata(fields: Vec<(&str, MetadataValue)>) -> Metadata {
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect::<HashMap<_, _>>()
+            .into()
+    }
+
+    #[test]
+    fn pid_matches_the_whole_bus_name_suffix() {
+        assert!(bus_name_matches_pid(
+            "org.mpris.MediaPlayer2.chromium.instance12",
+            12
+        ));
+        assert!(!bus_name_matches_pid(
+            "org.mpris.MediaPlayer2.chromium.instance123",
+            12
+        ));
+        assert!(!bus_name_matches_pid(
+            "org.mpris.MediaPlayer2.chromium.instance312",
+            12
+        ));
+    }
+
+    #[test]
+    fn bus_name_without_pid_matches_nothing() {
+        assert_eq!(pid_from_bus_name("org.mpris.MediaPlayer2.applemusic"), None);
+        assert!(!bus_name_matches_pid(
+            "org.mpris.MediaPlayer2.applemusic",
+            12
+        ));
+    }
+
+    /// Track length of metadata reporting the given `mpris:length`
+    fn length_of(length_in_us: MetadataValue) -> Option<u64> {
+        track_length_secs(metadata(vec![("mpris:length", length_in_us)]).length())
+    }
+
+    #[test]
+    fn track_length_is_read_from_microseconds() {
+        assert_eq!(length_of(MetadataValue::I64(215_500_000)), Some(215));
+        assert_eq!(length_of(MetadataValue::U64(215_500_000)), Some(215));
+    }
+
+    #[test]
+    fn missing_or_zero_track_length_is_unknown() {
+        assert_eq!(track_length_secs(Metadata::default().length()), None);
+        assert_eq!(length_of(MetadataValue::I64(0)), None);
+        // Under a second rounds down to zero
+        assert_eq!(length_of(MetadataValue::I64(999_999)), None);
+    }
+
+    #[test]
+    fn negative_or_overflowing_track_length_is_unknown() {
+        // The mpris crate casts a negative length to a huge unsigned one
+        assert_eq!(length_of(MetadataValue::I64(-1)), None);
+        assert_eq!(length_of(MetadataValue::I64(i64::MIN)), None);
+        assert_eq!(length_of(MetadataValue::U64(u64::MAX)), None);
+    }
+
+    #[test]
+    fn track_length_over_a_day_is_unknown() {
+        let day_in_us = MAX_TRACK_LENGTH_SECS as i64 * 1_000_000;
+        assert_eq!(
+            length_of(MetadataValue::I64(day_in_us)),
+            Some(MAX_TRACK_LENGTH_SECS)
+        );
+        assert_eq!(length_of(MetadataValue::I64(day_in_us + 1_000_000)), None);
+    }
+
+    /// Cached song with the given track id, title and reported artist
+    fn song(track_id: Option<&str>, title: &str, artist: &str) -> SongInfo {
+        SongInfo {
+            track_id: track_id.map(str::to_string),
+            title: title.to_string(),
+            artist: artist.to_string(),
+            reported_artist: artist.to_string(),
+            album: None,
+            album_artist: None,
+            start_time: 0,
+            end_time: None,
+            artwork_url: None,
+            apple_music_url: String::new(),
+            paused_at: None,
+            playback_modes: String::new(),
+            is_podcast: false,
+            last_updated: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn same_track_falls_back_to_title_and_artist_without_ids() {
+        let cached = song(None, "Title", "Artist");
+        assert!(cached.is_same_track(None, "Title", "Artist"));
+        assert!(!cached.is_same_track(None, "Other", "Artist"));
+        assert!(!cached.is_same_track(None, "Title", "Other"));
+
+        // An id on one side only can't be compared either
+        assert!(cached.is_same_track(Some("/track/1"), "Title", "Artist"));
+        let cached = song(Some("/track/1"), "Title", "Artist");
+        assert!(cached.is_same_track(None, "Title", "Artist"));
+        assert!(!cached.is_same_track(None, "Other", "Artist"));
+    }
+
+    #[test]
+    fn same_track_id_is_the_same_track() {
+        let cached = song(Some("/track/1"), "Title", "Artist");
+        assert!(cached.is_same_track(Some("/track/1"), "Title", "Artist"));
+        // Live metadata jitter doesn't make it another track
+        assert!(cached.is_same_track(Some("/track/1"), "Title (Live)", "Artist"));
+    }
+
+    #[test]
+    fn different_track_ids_are_different_tracks() {
+        let cached = song(Some("/track/1"), "Title", "Artist");
+        assert!(!cached.is_same_track(Some("/track/2"), "Title", "Artist"));
+    }
+
+    #[test]
+    fn no_track_id_is_no_id() {
+        assert_eq!(
+            track_id(&Metadata::new(
+                "/org/chromium/MediaPlayer2/TrackList/Track1"
+            ))
+            .as_deref(),
+            Some("/org/chromium/MediaPlayer2/TrackList/Track1")
+        );
+        assert_eq!(track_id(&Metadata::new(NO_TRACK_ID)), None);
+        assert_eq!(track_id(&Metadata::default()), None);
+    }
+
+    /// Metadata of a regular track, three minutes long
+    fn track_metadata(track_id: &str, title: &str, artist: &str) -> Metadata {
+        metadata(vec![
+            ("mpris:trackid", MetadataValue::String(track_id.to_string())),
+            ("xesam:title", MetadataValue::String(title.to_string())),
+            (
+                "xesam:artist",
+                MetadataValue::Array(vec![MetadataValue::String(artist.to_string())]),
+            ),
+            ("xesam:album", MetadataValue::String("Album".to_string())),
+            ("mpris:length", MetadataValue::I64(180_000_000)),
+        ])
+    }
+
+    /// Artwork provider answering every query with the same cover, counting the queries
+    struct FixedArtwork(std::cell::Cell<usize>);
+
+    impl ArtworkProvider for FixedArtwork {
+        fn artwork_url(&self, _artist: &str, _title: &str, _album: Option<&str>) -> Option<String> {
+            self.0.set(self.0.get() + 1);
+            Some("https://example.com/cover.jpg".to_string())
+        }
+    }
+
+    /// Forget the song cached by an earlier test
+    fn clear_song_cache() {
+        *CURRENT_SONG
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    }
+
+    /// Update the presence from the fake player, like the real update does from a snapshot
+    fn update_from(player: &FakePlayer, providers: &dyn ArtworkProvider) -> Result<String> {
+        update_presence_from(player, player.snapshot()?, providers)
+    }
+
+    #[test]
+    fn playing_track_updates_the_presence() {
+        let _state = testing::with_config(Config::default());
+        RecordingSink::start();
+        clear_song_cache();
+
+        let player = FakePlayer::playing(
+            track_metadata("/track/1", "Title", "Artist"),
+            Duration::from_secs(30),
+        );
+        let artwork = FixedArtwork(Default::default());
+        let before = unix_now();
+        update_from(&player, &artwork).unwrap();
+        let after = unix_now();
+
+        let recorded = RecordingSink::take();
+        assert_eq!(recorded.len(), 1);
+        let activity = recorded[0].as_ref().unwrap();
+        assert_eq!(activity["details"], "Title");
+        assert_eq!(activity["state"], "Artist");
+        assert_eq!(
+            activity["assets"]["large_image"],
+            "https://example.com/cover.jpg"
+        );
+        assert_eq!(activity["assets"]["large_text"], "Album");
+        assert_eq!(
+            activity["buttons"][0]["url"],
+            "https://music.apple.com/search?term=Title%20Artist"
+        );
+
+        // The progress bar starts where the player is and lasts the track length
+        let start = activity["timestamps"]["start"].as_i64().unwrap();
+        let end = activity["timestamps"]["end"].as_i64().unwrap();
+        assert!((before - 30..=after - 30).contains(&start));
+        assert_eq!(end - start, 180);
+        assert_eq!(artwork.0.get(), 1);
+    }
+
+    #[test]
+    fn same_track_reuses_the_cached_song() {
+        let _state = testing::with_config(Config::default());
+        RecordingSink::start();
+        clear_song_cache();
+
+        let artwork = FixedArtwork(Default::default());
+        let mut player = FakePlayer::playing(
+            track_metadata("/track/1", "Title", "Artist"),
+            Duration::from_secs(30),
+        );
+        update_from(&player, &artwork).unwrap();
+
+        // A seek alone doesn't look the artwork up again nor move the timestamps
+        player.position = Duration::from_secs(90);
+        update_from(&player, &artwork).unwrap();
+
+        let recorded = RecordingSink::take();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(
+            recorded[0].as_ref().unwrap()["timestamps"],
+            recorded[1].as_ref().unwrap()["timestamps"]
+        );
+        assert_eq!(artwork.0.get(), 1);
+
+        // Another track id is another song, even with the same title and artist
+        player.metadata = track_metadata("/track/2", "Title", "Artist");
+        update_from(&player, &artwork).unwrap();
+        assert_eq!(artwork.0.get(), 2);
+    }
+
+    #[test]
+    fn paused_player_leaves_the_presence_alone() {
+        let _state = testing::with_config(Config::default());
+        RecordingSink::start();
+        clear_song_cache();
+
+        let mut player = FakePlayer::playing(
+            track_metadata("/track/1", "Title", "Artist"),
+            Duration::ZERO,
+        );
+        player.status = PlaybackStatus::Paused;
+
+        assert!(update_from(&player, &FixedArtwork(Default::default())).is_err());
+        assert!(RecordingSink::take().is_empty());
+    }
+
+    #[test]
+    fn missing_metadata_shows_the_placeholder() {
+        let _state = testing::with_config(Config {
+            unknown_track_template: "Browsing Apple Music".to_string(),
+            ..Config::default()
+        });
+        RecordingSink::start();
+        clear_song_cache();
+
+        let player = FakePlayer::playing(Metadata::new("/track/1"), Duration::ZERO);
+        update_from(&player, &FixedArtwork(Default::default())).unwrap();
+
+        let recorded = RecordingSink::take();
+        assert_eq!(recorded.len(), 1);
+        let activity = recorded[0].as_ref().unwrap();
+        assert_eq!(activity["details"], "Browsing Apple Music");
+        assert!(activity.get("state").is_none());
+    }
+
+    #[test]
+    fn playback_modes_are_appended_to_the_state() {
+        let _state = testing::with_config(Config {
+            show_playback_modes: true,
+            ..Config::default()
+        });
+        RecordingSink::start();
+        clear_song_cache();
+
+        let mut player = FakePlayer::playing(
+            track_metadata("/track/1", "Title", "Artist"),
+            Duration::ZERO,
+        );
+        player.shuffle = true;
+        player.loop_status = LoopStatus::Track;
+        update_from(&player, &FixedArtwork(Default::default())).unwrap();
+
+        let recorded = RecordingSink::take();
+        assert_eq!(recorded[0].as_ref().unwrap()["state"], "Artist 🔀 🔂");
+    }
+
+    /// iTunes search answer for the smoke test, artwork in the size iTunes returns it in
+    const ITUNES_RESPONSE: &str = r#"{
+        "resultCount": 1,
+        "results": [{
+            "artistName": "M83",
+            "trackName": "Midnight City",
+            "artworkUrl100": "https://is1-ssl.mzstatic.com/image/thumb/Music/cover/100x100bb.jpg"
+        }]
+    }"#;
+
+    /// iTunes lookup without the disk cache, so the test doesn't touch the user's cache
+    struct UncachedItunes;
+
+    impl ArtworkProvider for UncachedItunes {
+        fn artwork_url(&self, artist: &str, title: &str, _album: Option<&str>) -> Option<String> {
+            artwork::get_artwork_url(artist, title)
+        }
+    }
+
+    #[test]
+    fn stored_pid_is_only_cleared_by_its_own_player() {
+        let _state = testing::with_config(Config::default());
+
+        // The restarted instance was stored before the old one shut down
+        store_pid(2_000).unwrap();
+        assert!(!clear_pid_if(1_000));
+        assert_eq!(get_pid().unwrap(), 2_000);
+
+        assert!(clear_pid_if(2_000));
+        assert!(get_pid().is_err());
+    }
+
+    #[test]
+    fn config_to_presence_smoke_test() {
+        let (itunes_url, requests) = testing::serve_once(Duration::ZERO, ITUNES_RESPONSE);
+        let _state = testing::with_config(Config {
+            itunes_api_url: itunes_url,
+            storefront: Some("gb".to_string()),
+            artwork_size: 600,
+            details_template: "{title} · {album}".to_string(),
+            state_template: "by {artist}".to_string(),
+            small_text: "amusic".to_string(),
+            activity_type: config::ActivityType::Playing,
+            extra_button_label: Some("YouTube Music".to_string()),
+            extra_button_url: Some(
+                "https://music.youtube.com/search?q={title}%20{artist}".to_string(),
+            ),
+            ..Config::default()
+        });
+        RecordingSink::start();
+        clear_song_cache();
+
+        let player = FakePlayer::playing(
+            metadata(vec![
+                (
+                    "mpris:trackid",
+                    MetadataValue::String("/track/1".to_string()),
+                ),
+                (
+                    "xesam:title",
+                    MetadataValue::String("Midnight City".to_string()),
+                ),
+                (
+                    "xesam:artist",
+                    MetadataValue::Array(vec![MetadataValue::String("M83".to_string())]),
+                ),
+                (
+                    "xesam:album",
+                    MetadataValue::String("Hurry Up, We're Dreaming".to_string()),
+                ),
+                ("mpris:length", MetadataValue::I64(243_000_000)),
+            ]),
+            Duration::from_secs(60),
+        );
+        let before = unix_now();
+        update_from(&player, &UncachedItunes).unwrap();
+        let after = unix_now();
+
+        // iTunes was searched at the configured mirror, in the configured storefront
+        assert_eq!(
+            requests.try_recv().unwrap(),
+            "/search?term=M83%20Midnight%20City&media=music&limit=1&country=gb"
+        );
+
+        let recorded = RecordingSink::take();
+        assert_eq!(recorded.len(), 1);
+        let mut activity = recorded[0].clone().unwrap();
+
+        let timestamps = activity
+            .as_object_mut()
+            .unwrap()
+            .remove("timestamps")
+            .unwrap();
+        let start = timestamps["start"].as_i64().unwrap();
+        assert!((before - 60..=after - 60).contains(&start));
+        assert_eq!(timestamps["end"].as_i64().unwrap() - start, 243);
+
+        assert_eq!(
+            activity,
+            serde_json::json!({
+                "details": "Midnight City · Hurry Up, We're Dreaming",
+                "state": "by M83",
+                "type": 0,
+                "assets": {
+                    "large_image": "https://is1-ssl.mzstatic.com/image/thumb/Music/cover/600x600bb.jpg",
+                    "large_text": "Hurry Up, We're Dreaming",
+                    "small_image": "amusic_lg",
+                    "small_text": "amusic",
+                },
+                "buttons": [
+                    {
+                        "label": "Play in Apple Music",
+                        "url": "https://music.apple.com/gb/search?term=Midnight%20City%20M83",
+                    },
+                    {
+                        "label": "YouTube Music",
+                        "url": "https://music.youtube.com/search?q=Midnight%20City%20M83",
+                    },
+                ],
+            })
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use crate::apple_music::{launcher, player};
+use crate::commands;
+use crate::config;
+use nix::sys::signal::{SigSet, Signal};
+use tracing::{error, info, warn};
+
+/// Action requested on the command line
+#[derive(Debug, PartialEq, Eq)]
+pub enum CliAction {
+    /// Start the full application
+    Run,
+    /// Print the current track as JSON and exit
+    NowPlaying,
+    /// Run the presence without the tray and window, e.g. as a service
+    Headless,
+    /// Print the version and exit
+    Version,
+    /// Print the usage and exit
+    Help,
+}
+
+/// Usage printed by `--help`
+const USAGE: &str = "\
+Usage: amusic [OPTION]
+
+Discord Rich Presence for Apple Music
+
+Options:
+      --headless     Run the presence without the tray and window, e.g. as a service
+      --now-playing  Print the current track as JSON and exit
+  -V, --version      Print the version and exit
+  -h, --help         Print this help and exit
+
+The configuration is read from config.toml in the amusic config directory.";
+
+/// Parse the command line arguments, skipping the binary name
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> CliAction {
+    for arg in args.into_iter().skip(1) {
+        match arg.as_str() {
+            "--now-playing" => return CliAction::NowPlaying,
+            "--headless" => return CliAction::Headless,
+            "--version" | "-V" => return CliAction::Version,
+            "--help" | "-h" => return CliAction::Help,
+            _ => {}
+        }
+    }
+
+    CliAction::Run
+}
+
+/// Text printed by `--version`
+pub fn version() -> String {
+    format!("amusic {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Text printed by `--help`
+pub fn usage() -> &'static str {
+    USAGE
+}
+
+/// Print the current track as JSON and return the process exit code
+pub fn print_now_playing() -> i32 {
+    // We didn't launch the browser ourselves, so adopt the running instance
+    match launcher::find_running_pid() {
+        Some(pid) => {
+            if let Err(e) = player::store_pid(pid) {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+        None => {
+            eprintln!("Apple Music is not running");
+            return 1;
+        }
+    }
+
+    let now_playing = match player::read_now_playing() {
+        Ok(now_playing) => now_playing,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    match serde_json::to_string(&now_playing) {
+        Ok(json) => {
+            println!("{}", json);
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to serialize track: {}", e);
+            1
+        }
+    }
+}
+
+/// Block SIGINT and SIGTERM on the calling thread and every thread it starts afterwards,
+/// so only `wait_for_shutdown` receives them
+pub fn block_shutdown_signals() -> nix::Result<SigSet> {
+    let mut signals = SigSet::empty();
+    signals.add(Signal::SIGINT);
+    signals.add(Signal::SIGTERM);
+    signals.thread_block()?;
+    Ok(signals)
+}
+
+/// Wait for SIGINT or SIGTERM, then clear the presence and kill Apple Music like the tray's Quit item
+pub fn wait_for_shutdown(signals: &SigSet) {
+    match signals.wait() {
+        Ok(signal) => info!("Received {:?}, shutting down", signal),
+        Err(e) => error!("Failed to wait for shutdown signals: {}", e),
+    }
+
+    launcher::shutdown();
+}
+
+/// Run the Discord presence and MPRIS threads until SIGINT or SIGTERM, returning the process exit code
+pub fn run_headless() -> i32 {
+    // Block the shutdown signals before any thread starts
+    let signals = match block_shutdown_signals() {
+        Ok(signals) => signals,
+        Err(e) => {
+            error!("Failed to block shutdown signals: {}", e);
+            return 1;
+        }
+    };
+
+    info!("Running headless, without the tray");
+    if config::get().auto_launch {
+        if let Err(e) = launcher::open_apple_music() {
+            error!("{}", e);
+            return 1;
+        }
+    } else if let Err(e) = commands::start_discord_presence() {
+        // The periodic thread keeps trying to reach Discord
+        warn!("{}", e);
+    }
+
+    wait_for_shutdown(&signals);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse a command line given without the binary name
+    fn parse(args: &[&str]) -> CliAction {
+        parse_args(
+            std::iter::once("amusic")
+                .chain(args.iter().copied())
+                .map(str::to_string),
+        )
+    }
+
+    #[test]
+    fn command_line_actions() {
+        let cases: &[(&[&str], CliAction)] = &[
+            (&[], CliAction::Run),
+            (&["--help"], CliAction::Help),
+            (&["-h"], CliAction::Help),
+            (&["--version"], CliAction::Version),
+            (&["-V"], CliAction::Version),
+            (&["--headless"], CliAction::Headless),
+            (&["--now-playing"], CliAction::NowPlaying),
+            // Unknown arguments, e.g. added by a desktop launcher, are ignored
+            (&["--unknown"], CliAction::Run),
+            (&["--unknown", "--version"], CliAction::Version),
+            // The first recognized flag wins
+            (&["--headless", "--help"], CliAction::Headless),
+        ];
+
+        for (args, action) in cases {
+            assert_eq!(&parse(args), action, "amusic {}", args.join(" "));
+        }
+    }
+
+    #[test]
+    fn binary_name_is_not_parsed_as_a_flag() {
+        assert_eq!(parse_args(["--help".to_string()]), CliAction::Run);
+    }
+}
@@ -1,26 +1,175 @@
+use crate::apple_music::{self, history, player};
+use crate::config::{self, Config};
+use crate::diagnostics::{self, Diagnostics};
 use crate::discord;
+use crate::ui;
+use std::sync::Once;
+use tracing::{debug, info, warn};
+
+// The presence threads are only started once, even when Discord is retried later
+static START_THREADS: Once = Once::new();
 
 /// Tauri command to start Discord presence
 #[tauri::command]
 pub fn start_discord_presence() -> std::result::Result<String, String> {
-    // Initialize the Discord client
-    match discord::initialize() {
-        Ok(_) => {
-            // Start periodic updates thread
-            discord::start_periodic_updates();
+    // Start the threads even when Discord is unreachable, the periodic thread keeps retrying it
+    START_THREADS.call_once(|| {
+        // Start periodic updates thread
+        discord::start_periodic_updates();
+
+        // Start heartbeat thread re-asserting the activity during long tracks
+        discord::start_heartbeat();
 
-            // Start MPRIS event listener thread
-            crate::apple_music::player::start_event_listener();
+        // Start MPRIS event listener thread
+        crate::apple_music::player::start_event_listener();
 
+        // Start rescan thread attaching to manually opened Apple Music instances
+        player::start_player_rescan();
+    });
+
+    // Initialize the Discord client
+    let result = match discord::initialize() {
+        Ok(_) => {
             // Try to update presence with current player state, if any
             match crate::apple_music::player::update_discord_presence() {
                 Ok(msg) => Ok(msg),
                 Err(e) => {
-                    println!("No active player or media found on startup: {}", e);
+                    debug!("No active player or media found on startup: {}", e);
                     Ok("Discord presence initialized. Waiting for media playback...".to_string())
                 }
             }
         }
         Err(e) => Err(format!("Failed to initialize Discord: {}", e)),
+    };
+
+    // Let the tray reflect whether Discord is available
+    ui::refresh_tray_menu();
+
+    result
+}
+
+/// Tauri command to retry connecting to Discord after it was disabled
+#[tauri::command]
+pub fn retry_discord() -> std::result::Result<String, String> {
+    info!("Retrying Discord connection");
+    start_discord_presence()
+}
+
+/// Tauri command to pause or resume broadcasting the Discord presence
+/// Returns whether the presence is enabled afterwards
+#[tauri::command]
+pub fn set_presence_enabled(enabled: bool) -> std::result::Result<bool, String> {
+    let result = discord::set_presence_enabled(enabled).map_err(|e| e.to_string());
+
+    // Let the tray toggle reflect the new state
+    ui::refresh_tray_menu();
+
+    // Show the current track right away instead of waiting for the next player event
+    if enabled {
+        std::thread::spawn(|| {
+            let _ = apple_music::update_discord_presence();
+        });
+    }
+
+    result.map(|_| discord::is_presence_enabled())
+}
+
+/// Tauri command to force a presence update from the current player state
+#[tauri::command]
+pub fn refresh_presence() -> std::result::Result<String, String> {
+    apple_music::update_discord_presence().map_err(|e| e.to_string())
+}
+
+/// Tauri command to get the configuration in use, e.g. for a settings window
+#[tauri::command]
+pub fn get_config() -> Config {
+    config::get()
+}
+
+/// Tauri command to save the configuration to the config file and apply it
+/// Returns the names of the changed fields that only take effect after a restart
+#[tauri::command]
+pub fn set_config(config: Config) -> std::result::Result<Vec<String>, String> {
+    let restart_fields = config::update(config).map_err(|e| e.to_string())?;
+    info!("Configuration updated from the frontend");
+
+    // Re-render the presence with the new templates and buttons
+    std::thread::spawn(|| {
+        let _ = apple_music::update_discord_presence();
+    });
+
+    Ok(restart_fields.into_iter().map(str::to_string).collect())
+}
+
+/// Tauri command to get the currently playing track
+#[tauri::command]
+pub fn get_current_track() -> std::result::Result<player::TrackInfo, String> {
+    player::current_track().map_err(|e| e.to_string())
+}
+
+/// Tauri command to list every MPRIS player on the session bus, for debugging player detection
+#[tauri::command]
+pub fn list_mpris_players() -> Vec<player::PlayerSummary> {
+    match player::list_players() {
+        Ok(players) => players,
+        Err(e) => {
+            warn!("Could not list MPRIS players: {}", e);
+            Vec::new()
+        }
     }
 }
+
+/// Tauri command to report internal state, for bug reports
+#[tauri::command]
+pub fn get_diagnostics() -> Diagnostics {
+    diagnostics::collect()
+}
+
+/// Tauri command to get the Apple Music volume, between 0.0 and 1.0
+#[tauri::command]
+pub fn get_volume() -> std::result::Result<f64, String> {
+    player::get_volume().map_err(|e| e.to_string())
+}
+
+/// Tauri command to set the Apple Music volume, clamped between 0.0 and 1.0
+#[tauri::command]
+pub fn set_volume(level: f64) -> std::result::Result<f64, String> {
+    player::set_volume(level).map_err(|e| e.to_string())
+}
+
+/// Tauri command to export the listening history of this session to a JSON or CSV file
+#[tauri::command]
+pub fn export_history(path: String) -> std::result::Result<usize, String> {
+    history::export(std::path::Path::new(&path)).map_err(|e| e.to_string())
+}
+
+/// Tauri command to get up to `limit` of the tracks played this session, newest first
+#[tauri::command]
+pub fn get_play_history(limit: usize) -> std::result::Result<Vec<history::RecentTrack>, String> {
+    history::latest(limit).map_err(|e| e.to_string())
+}
+
+/// Tauri command to clear the listening history of this session
+#[tauri::command]
+pub fn clear_history() -> std::result::Result<(), String> {
+    history::clear().map_err(|e| e.to_string())?;
+    ui::refresh_tray_menu();
+    Ok(())
+}
+
+/// Tauri command to re-open Apple Music if our instance was closed
+#[tauri::command]
+pub fn ensure_apple_music_open() -> std::result::Result<String, String> {
+    match apple_music::ensure_apple_music_open() {
+        Ok(true) => Ok("Apple Music relaunched".to_string()),
+        Ok(false) => Ok("Apple Music is already running".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Tauri command to restart Apple Music, e.g. when the web player got stuck
+#[tauri::command]
+pub fn restart_apple_music() -> std::result::Result<String, String> {
+    apple_music::restart_apple_music().map_err(|e| e.to_string())?;
+    Ok("Apple Music restarted".to_string())
+}
@@ -1,3 +1,5 @@
+//! Default configuration values, used when the config file doesn't set a field
+
 /// Discord application client ID
 pub const DISCORD_CLIENT_ID: &str = "1354665491792138350";
 
@@ -6,3 +8,126 @@ pub const APPLE_MUSIC_URL: &str = "https://music.apple.com";
 
 /// iTunes search API URL
 pub const ITUNES_SEARCH_API_URL: &str = "https://itunes.apple.com/search";
+
+/// Browsers tried when launching Apple Music, in preference order
+pub const BROWSERS: &[&str] = &[
+    "chromium",
+    "brave",
+    "brave-browser",
+    "google-chrome",
+    "google-chrome-stable",
+    "vivaldi",
+    "vivaldi-stable",
+    "firefox",
+];
+
+/// Timeout of artwork lookups, in seconds
+pub const TIMEOUT_SECS: u64 = 8;
+
+/// Interval between progress bar refreshes, in seconds
+pub const POLL_INTERVAL_SECS: u64 = 10;
+
+/// Duration assumed for tracks of unknown length, in seconds
+pub const DEFAULT_TRACK_SECS: u64 = 180;
+
+/// Interval between progress bar refreshes while the song length is unknown, in seconds
+pub const FAST_RETRY_SECS: u64 = 2;
+
+/// How long cached song information stays fresh, in seconds
+pub const CACHE_TTL_SECS: u64 = 30;
+
+/// Artist placeholder used when MPRIS does not report one
+pub const UNKNOWN_ARTIST: &str = "Unknown";
+
+/// Delay before clearing the presence after a pause or stop, in milliseconds
+pub const IDLE_CLEAR_DELAY_MS: u64 = 1500;
+
+/// Time track changes must settle before the presence is updated, in milliseconds
+pub const TRACK_CHANGE_DEBOUNCE_MS: u64 = 800;
+
+/// Refresh interval of the MPRIS progress tracker, in milliseconds
+pub const PROGRESS_TICK_MS: u32 = 1000;
+
+/// Separator used to join multiple artists
+pub const ARTIST_SEPARATOR: &str = ", ";
+
+/// Number of tracks shown in the tray's recent tracks submenu
+pub const RECENT_TRACKS_LIMIT: usize = 10;
+
+/// Number of tracks kept in the session history
+pub const HISTORY_LIMIT: usize = 1000;
+
+/// Clear the presence on pause instead of showing the track as paused
+pub const CLEAR_ON_PAUSE: bool = false;
+
+/// Show a desktop notification when a new track starts
+pub const NOTIFICATIONS_ENABLED: bool = false;
+
+/// Artwork providers to try, in order. Known providers: "mpris", "itunes", "musicbrainz"
+pub const ARTWORK_PROVIDERS: &[&str] = &["mpris", "itunes", "musicbrainz"];
+
+/// Edge length of the iTunes artwork, in pixels
+pub const ARTWORK_SIZE: u32 = 600;
+
+/// How long found artwork stays in the disk cache, in seconds
+pub const ARTWORK_CACHE_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// How long lookups that found no artwork stay in the disk cache, in seconds
+pub const ARTWORK_NEGATIVE_CACHE_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Interval at which the current activity is re-asserted while playing, in seconds
+pub const HEARTBEAT_INTERVAL_SECS: u64 = 120;
+
+/// Maximum time spent looking for the MPRIS player, in seconds
+pub const FINDER_TIMEOUT_SECS: u64 = 5;
+
+/// Media URLs allowed to drive the presence, matched as substrings. Empty allows any URL
+pub const URL_ALLOWLIST: &[&str] = &["music.apple.com"];
+
+/// Titles that never drive the presence, matched case-insensitively as substrings
+pub const TITLE_BLOCKLIST: &[&str] = &[];
+
+/// Connection attempts made before Discord presence is disabled
+pub const DISCORD_CONNECT_ATTEMPTS: u32 = 5;
+
+/// Delay before the first Discord connection retry, doubled after each failure, in seconds
+pub const DISCORD_CONNECT_RETRY_SECS: u64 = 2;
+
+/// Asset key of the small Discord image
+pub const SMALL_IMAGE_KEY: &str = "amusic_lg";
+
+/// Hover text of the small Discord image
+pub const SMALL_TEXT: &str = "Apple Music";
+
+/// Asset key of the large Discord image, shown when no artwork was found
+pub const LARGE_IMAGE_FALLBACK_KEY: &str = "amusic_lg";
+
+/// Label of the Discord button opening the track in Apple Music
+pub const PLAY_BUTTON_LABEL: &str = "Play in Apple Music";
+
+/// Template of the presence details line. Tokens: {title}, {artist}, {album}, {album_artist}
+pub const DETAILS_TEMPLATE: &str = "{title}";
+
+/// Template of the presence state line. Tokens: {title}, {artist}, {album}, {album_artist}
+pub const STATE_TEMPLATE: &str = "{artist}";
+
+/// Template of the presence details line for podcasts. Tokens: {episode}, {show}, {album}
+pub const PODCAST_DETAILS_TEMPLATE: &str = "{episode}";
+
+/// Template of the presence state line for podcasts. Tokens: {episode}, {show}, {album}
+pub const PODCAST_STATE_TEMPLATE: &str = "{show}";
+
+/// Presence text shown when the player reports neither a title nor an artist
+pub const UNKNOWN_TRACK_TEMPLATE: &str = "Listening to Apple Music";
+
+/// Services listens are submitted to, once their credentials are configured
+pub const SCROBBLE_SERVICES: &[&str] = &["lastfm", "listenbrainz"];
+
+/// Template of the now playing file. Tokens: {title}, {artist}, {album}
+pub const NOWPLAYING_TEMPLATE: &str = "{artist} - {title}";
+
+/// Time given to a launched browser to expose its MPRIS player, in seconds
+pub const PLAYER_READY_TIMEOUT_SECS: u64 = 15;
+
+/// Interval between scans for a manually opened Apple Music instance, in seconds
+pub const PLAYER_RESCAN_INTERVAL_SECS: u64 = 15;
@@ -1 +1,517 @@
 pub mod constants;
+pub mod urls;
+
+use crate::error::{AppError, Result};
+use crate::scrobble;
+use crate::utils::artwork;
+use constants::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tracing::{debug, error, info, warn};
+
+/// What the Discord activity timestamps represent
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TimestampMode {
+    /// Progress bar with the time remaining in the current track
+    #[serde(alias = "TrackPosition")]
+    Remaining,
+    /// Time elapsed in the current track, without a progress bar
+    Elapsed,
+    /// Elapsed time since Apple Music was opened
+    SessionStart,
+    /// No timestamps at all
+    None,
+}
+
+/// How the Discord activity is labelled, e.g. "Listening to Apple Music"
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ActivityType {
+    /// "Listening to ..."
+    Listening,
+    /// "Playing ..."
+    Playing,
+}
+
+/// Format of the log lines written to stderr
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    Pretty,
+    /// One JSON object per event, for journald, Loki and other log aggregators
+    Json,
+}
+
+/// Runtime configuration, read from `config.toml` in the platform config directory
+/// Every field is optional in the file and falls back to the defaults in `constants`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Discord application client ID
+    pub discord_client_id: String,
+    /// Format of the logs: "pretty" or "json"
+    pub log_format: LogFormat,
+    /// URL for Apple Music
+    pub apple_music_url: String,
+    /// iTunes search API URL, or a mirror or proxy of it, e.g. an internal cache behind a corporate proxy
+    pub itunes_api_url: String,
+    /// Apple Music storefront as a country code, e.g. "us", "gb" or "jp". Unset uses the global endpoints
+    pub storefront: Option<String>,
+    /// Browsers tried when launching Apple Music, in preference order
+    pub browsers: Vec<String>,
+    /// Timeout of artwork lookups, in seconds
+    pub timeout_secs: u64,
+    /// Interval between progress bar refreshes, in seconds
+    pub poll_interval_secs: u64,
+    /// Interval between progress bar refreshes while the song length is unknown, in seconds
+    pub fast_retry_secs: u64,
+    /// How long cached song information stays fresh, in seconds
+    pub cache_ttl_secs: u64,
+    /// Delay before clearing the presence after a pause or stop, in milliseconds
+    pub idle_clear_delay_ms: u64,
+    /// Time track changes must settle before the presence is updated, in milliseconds
+    pub track_change_debounce_ms: u64,
+    /// Longest wait of the event listener's progress tracker for player changes, in milliseconds
+    pub progress_tick_ms: u32,
+    /// Separator used to join multiple artists
+    pub artist_separator: String,
+    /// Number of tracks shown in the tray's recent tracks submenu
+    pub recent_tracks_limit: usize,
+    /// Number of tracks kept in the session history
+    pub history_limit: usize,
+    /// Clear the presence on pause instead of showing the track as paused
+    pub clear_on_pause: bool,
+    /// Show a desktop notification when a new track starts
+    pub notifications_enabled: bool,
+    /// Artwork providers to try, in order. Known providers: "mpris", "itunes", "musicbrainz"
+    pub artwork_providers: Vec<String>,
+    /// Serve local MPRIS artwork over a loopback HTTP server instead of ignoring `file://` art URLs
+    /// Discord loads images through its media proxy, which may not reach the loopback address
+    pub serve_local_artwork: bool,
+    /// Edge length of the iTunes artwork, in pixels. One of the sizes iTunes serves, e.g. 600 or 1024
+    pub artwork_size: u32,
+    /// How long found artwork stays in the disk cache, in seconds
+    pub artwork_cache_ttl_secs: u64,
+    /// How long lookups that found no artwork stay in the disk cache, in seconds
+    pub artwork_negative_cache_ttl_secs: u64,
+    /// Interval at which the current activity is re-asserted while playing, in seconds
+    pub heartbeat_interval_secs: u64,
+    /// Maximum time spent looking for the MPRIS player, in seconds
+    pub finder_timeout_secs: u64,
+    /// Media URLs allowed to drive the presence, matched as substrings. Empty allows any URL
+    pub url_allowlist: Vec<String>,
+    /// Titles that never drive the presence, matched case-insensitively as substrings
+    pub title_blocklist: Vec<String>,
+    /// Connection attempts made before Discord presence is disabled
+    pub discord_connect_attempts: u32,
+    /// Delay before the first Discord connection retry, doubled after each failure, in seconds
+    pub discord_connect_retry_secs: u64,
+    /// Asset key of the small Discord image
+    pub small_image_key: String,
+    /// Hover text of the small Discord image
+    #[serde(alias = "small_image_text")]
+    pub small_text: String,
+    /// Asset key of the large Discord image, shown when no artwork was found
+    pub large_image_fallback_key: String,
+    /// Label of the Discord button opening the track in Apple Music
+    pub play_button_label: String,
+    /// Label of an optional second Discord button, e.g. "Search on YouTube Music"
+    pub extra_button_label: Option<String>,
+    /// URL template of the second button. Tokens: {title}, {artist}, filled in URL-encoded
+    pub extra_button_url: Option<String>,
+    /// Point the "Play" button to the track's song.link page, so it opens on any streaming service
+    /// Falls back to the Apple Music search when the track can't be resolved
+    pub songlink_enabled: bool,
+    /// Skip every network artwork lookup, using only the MPRIS art URL or the default asset
+    pub offline: bool,
+    /// Template of the presence details line. Tokens: {title}, {artist}, {album}, {album_artist}
+    pub details_template: String,
+    /// Template of the presence state line. Tokens: {title}, {artist}, {album}, {album_artist}
+    pub state_template: String,
+    /// Details template used while paused, e.g. "⏸ {title}". Falls back to `details_template`
+    pub paused_details_template: Option<String>,
+    /// State template used while paused. Falls back to `state_template`
+    pub paused_state_template: Option<String>,
+    /// Presence text shown when the player reports neither a title nor an artist
+    pub unknown_track_template: String,
+    /// Recognize podcast episodes by their genre, or by a long length without an album
+    pub detect_podcasts: bool,
+    /// Template of the presence details line for podcasts. Tokens: {episode}, {show}, {album}
+    pub podcast_details_template: String,
+    /// Template of the presence state line for podcasts. Tokens: {episode}, {show}, {album}
+    pub podcast_state_template: String,
+    /// How the Discord activity is labelled: "Listening" or "Playing"
+    pub activity_type: ActivityType,
+    /// Append shuffle and repeat glyphs to the presence state line
+    pub show_playback_modes: bool,
+    /// What the Discord activity timestamps represent
+    pub timestamp_mode: TimestampMode,
+    /// Show a progress bar of `default_track_secs` when the track length is unknown, instead of only the elapsed time
+    pub show_progress_when_unknown: bool,
+    /// Duration assumed for tracks of unknown length, in seconds
+    pub default_track_secs: u64,
+    /// Launch Apple Music on startup. When disabled, amusic attaches to an instance opened manually
+    pub auto_launch: bool,
+    /// Launch Apple Music again when its window is closed, at most 3 times per minute
+    pub relaunch_on_close: bool,
+    /// Time given to a launched browser to expose its MPRIS player, in seconds
+    pub player_ready_timeout_secs: u64,
+    /// Only accept the player whose bus name carries the browser PID, without falling back
+    /// to recognizing Apple Music by name. Useful when several browser music apps are running
+    pub strict_pid_matching: bool,
+    /// Interval between scans for a manually opened Apple Music instance, in seconds
+    pub player_rescan_interval_secs: u64,
+    /// MPRIS-enabling browser flags replacing the built-in per-browser defaults when set
+    pub mpris_flags_override: Option<Vec<String>>,
+    /// Extra browser flags appended after the built-in ones, e.g. "--ozone-platform=wayland"
+    pub extra_browser_args: Vec<String>,
+    /// File the current track is written to, e.g. for OBS overlays. Emptied on pause and stop
+    pub nowplaying_file: Option<String>,
+    /// Template of the now playing file. Tokens: {title}, {artist}, {album}
+    pub nowplaying_template: String,
+    /// Services listens are submitted to: "lastfm", "listenbrainz". Each also needs its credentials
+    pub scrobble_services: Vec<String>,
+    /// Last.fm API key, Last.fm scrobbling is enabled once the key, secret and session key are set
+    pub lastfm_api_key: Option<String>,
+    /// Last.fm API shared secret
+    pub lastfm_api_secret: Option<String>,
+    /// Last.fm session key of the user to scrobble for
+    pub lastfm_session_key: Option<String>,
+    /// ListenBrainz user token
+    pub listenbrainz_token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+
+        Config {
+            discord_client_id: DISCORD_CLIENT_ID.to_string(),
+            log_format: LogFormat::Pretty,
+            apple_music_url: APPLE_MUSIC_URL.to_string(),
+            itunes_api_url: ITUNES_SEARCH_API_URL.to_string(),
+            storefront: None,
+            browsers: strings(BROWSERS),
+            timeout_secs: TIMEOUT_SECS,
+            poll_interval_secs: POLL_INTERVAL_SECS,
+            fast_retry_secs: FAST_RETRY_SECS,
+            cache_ttl_secs: CACHE_TTL_SECS,
+            idle_clear_delay_ms: IDLE_CLEAR_DELAY_MS,
+            track_change_debounce_ms: TRACK_CHANGE_DEBOUNCE_MS,
+            progress_tick_ms: PROGRESS_TICK_MS,
+            artist_separator: ARTIST_SEPARATOR.to_string(),
+            recent_tracks_limit: RECENT_TRACKS_LIMIT,
+            history_limit: HISTORY_LIMIT,
+            clear_on_pause: CLEAR_ON_PAUSE,
+            notifications_enabled: NOTIFICATIONS_ENABLED,
+            artwork_providers: strings(ARTWORK_PROVIDERS),
+            serve_local_artwork: false,
+            artwork_size: ARTWORK_SIZE,
+            artwork_cache_ttl_secs: ARTWORK_CACHE_TTL_SECS,
+            artwork_negative_cache_ttl_secs: ARTWORK_NEGATIVE_CACHE_TTL_SECS,
+            heartbeat_interval_secs: HEARTBEAT_INTERVAL_SECS,
+            finder_timeout_secs: FINDER_TIMEOUT_SECS,
+            url_allowlist: strings(URL_ALLOWLIST),
+            title_blocklist: strings(TITLE_BLOCKLIST),
+            discord_connect_attempts: DISCORD_CONNECT_ATTEMPTS,
+            discord_connect_retry_secs: DISCORD_CONNECT_RETRY_SECS,
+            small_image_key: SMALL_IMAGE_KEY.to_string(),
+            small_text: SMALL_TEXT.to_string(),
+            large_image_fallback_key: LARGE_IMAGE_FALLBACK_KEY.to_string(),
+            play_button_label: PLAY_BUTTON_LABEL.to_string(),
+            extra_button_label: None,
+            extra_button_url: None,
+            songlink_enabled: false,
+            offline: false,
+            details_template: DETAILS_TEMPLATE.to_string(),
+            state_template: STATE_TEMPLATE.to_string(),
+            paused_details_template: None,
+            paused_state_template: None,
+            unknown_track_template: UNKNOWN_TRACK_TEMPLATE.to_string(),
+            detect_podcasts: true,
+            podcast_details_template: PODCAST_DETAILS_TEMPLATE.to_string(),
+            podcast_state_template: PODCAST_STATE_TEMPLATE.to_string(),
+            activity_type: ActivityType::Listening,
+            show_playback_modes: false,
+            timestamp_mode: TimestampMode::Remaining,
+            show_progress_when_unknown: false,
+            default_track_secs: DEFAULT_TRACK_SECS,
+            auto_launch: true,
+            relaunch_on_close: false,
+            player_ready_timeout_secs: PLAYER_READY_TIMEOUT_SECS,
+            strict_pid_matching: false,
+            player_rescan_interval_secs: PLAYER_RESCAN_INTERVAL_SECS,
+            mpris_flags_override: None,
+            extra_browser_args: Vec::new(),
+            nowplaying_file: None,
+            nowplaying_template: NOWPLAYING_TEMPLATE.to_string(),
+            scrobble_services: strings(SCROBBLE_SERVICES),
+            lastfm_api_key: None,
+            lastfm_api_secret: None,
+            lastfm_session_key: None,
+            listenbrainz_token: None,
+        }
+    }
+}
+
+impl Config {
+    /// Replace zero intervals, which would make the threads spin or every lookup time out,
+    /// with their defaults
+    fn fix_zero_intervals(&mut self) {
+        let defaults = Config::default();
+        let intervals = [
+            (
+                "poll_interval_secs",
+                &mut self.poll_interval_secs,
+                defaults.poll_interval_secs,
+            ),
+            (
+                "fast_retry_secs",
+                &mut self.fast_retry_secs,
+                defaults.fast_retry_secs,
+            ),
+            (
+                "cache_ttl_secs",
+                &mut self.cache_ttl_secs,
+                defaults.cache_ttl_secs,
+            ),
+            (
+                "heartbeat_interval_secs",
+                &mut self.heartbeat_interval_secs,
+                defaults.heartbeat_interval_secs,
+            ),
+            (
+                "player_rescan_interval_secs",
+                &mut self.player_rescan_interval_secs,
+                defaults.player_rescan_interval_secs,
+            ),
+            (
+                "finder_timeout_secs",
+                &mut self.finder_timeout_secs,
+                defaults.finder_timeout_secs,
+            ),
+            (
+                "idle_clear_delay_ms",
+                &mut self.idle_clear_delay_ms,
+                defaults.idle_clear_delay_ms,
+            ),
+            (
+                "track_change_debounce_ms",
+                &mut self.track_change_debounce_ms,
+                defaults.track_change_debounce_ms,
+            ),
+        ];
+
+        for (name, value, default) in intervals {
+            fix_zero_interval(name, value, default);
+        }
+        fix_zero_interval(
+            "progress_tick_ms",
+            &mut self.progress_tick_ms,
+            defaults.progress_tick_ms,
+        );
+    }
+}
+
+/// Replace a zero interval by its default, warning about it
+fn fix_zero_interval<T>(name: &str, value: &mut T, default: T)
+where
+    T: Copy + Default + PartialEq + std::fmt::Display,
+{
+    if *value == T::default() {
+        warn!("{} must be greater than zero, using {}", name, default);
+        *value = default;
+    }
+}
+
+// Configuration in use, defaults until a config file is loaded
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<Config> = RwLock::new(Config::default());
+}
+
+/// Path of the config file, e.g. `~/.config/amusic/config.toml`
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("amusic").join("config.toml"))
+}
+
+/// Load the configuration from the config file, falling back to defaults when it is missing
+pub fn load_config() -> Result<Config> {
+    let Some(path) = config_path().filter(|path| path.exists()) else {
+        debug!("No config file found, using defaults");
+        return Ok(Config::default());
+    };
+
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| AppError::Application(format!("Failed to read {}: {}", path.display(), e)))?;
+
+    let mut config: Config = toml::from_str(&contents)
+        .map_err(|e| AppError::Application(format!("Failed to parse {}: {}", path.display(), e)))?;
+
+    validate(&config)?;
+    config.fix_zero_intervals();
+    info!("Loaded config from {}", path.display());
+
+    Ok(config)
+}
+
+/// Write a configuration to the config file
+pub fn save_config(config: &Config) -> Result<()> {
+    let path = config_path()
+        .ok_or_else(|| AppError::Application("No config directory available".into()))?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            AppError::Application(format!("Failed to create {}: {}", dir.display(), e))
+        })?;
+    }
+
+    let contents = toml::to_string_pretty(config)
+        .map_err(|e| AppError::Application(format!("Failed to serialize config: {}", e)))?;
+
+    std::fs::write(&path, contents)
+        .map_err(|e| AppError::Application(format!("Failed to write {}: {}", path.display(), e)))?;
+    info!("Saved config to {}", path.display());
+
+    Ok(())
+}
+
+/// Names of the fields that differ between two configurations and only take effect after a restart
+pub fn restart_required(current: &Config, updated: &Config) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if current.discord_client_id != updated.discord_client_id {
+        fields.push("discord_client_id");
+    }
+    if current.log_format != updated.log_format {
+        fields.push("log_format");
+    }
+    fields
+}
+
+/// Validate, save and apply a configuration
+/// Every other field is read on use, so it applies right away
+/// Returns the fields that need a restart to take effect
+pub fn update(mut config: Config) -> Result<Vec<&'static str>> {
+    validate(&config)?;
+    config.fix_zero_intervals();
+    save_config(&config)?;
+
+    let restart_fields = restart_required(&get(), &config);
+    set(config);
+
+    Ok(restart_fields)
+}
+
+/// Replace the configuration in use
+pub fn set(config: Config) {
+    match CONFIG.write() {
+        Ok(mut guard) => *guard = config,
+        Err(e) => error!("Failed to lock config: {}", e),
+    }
+}
+
+/// Get a copy of the configuration in use
+pub fn get() -> Config {
+    match CONFIG.read() {
+        Ok(guard) => guard.clone(),
+        Err(e) => {
+            error!("Failed to lock config: {}", e);
+            Config::default()
+        }
+    }
+}
+
+/// Validate a configuration before it is used
+pub fn validate(config: &Config) -> Result<()> {
+    urls::validate(config)?;
+    artwork::parse_providers(&config.artwork_providers)?;
+    artwork::validate_size(config.artwork_size)?;
+    scrobble::parse_services(&config.scrobble_services)?;
+    validate_browser_args(&config.extra_browser_args)?;
+    Ok(())
+}
+
+/// Check that every extra browser argument is a non-empty string
+fn validate_browser_args(args: &[String]) -> Result<()> {
+    if args.iter().any(|arg| arg.trim().is_empty()) {
+        return Err(AppError::Application(
+            "extra_browser_args must not contain empty arguments".into(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_intervals_fall_back_to_defaults() {
+        let mut config = Config {
+            poll_interval_secs: 0,
+            fast_retry_secs: 0,
+            cache_ttl_secs: 0,
+            heartbeat_interval_secs: 0,
+            player_rescan_interval_secs: 0,
+            finder_timeout_secs: 0,
+            ..Config::default()
+        };
+        config.fix_zero_intervals();
+
+        assert_eq!(config.poll_interval_secs, POLL_INTERVAL_SECS);
+        assert_eq!(config.fast_retry_secs, FAST_RETRY_SECS);
+        assert_eq!(config.cache_ttl_secs, CACHE_TTL_SECS);
+        assert_eq!(config.heartbeat_interval_secs, HEARTBEAT_INTERVAL_SECS);
+        assert_eq!(
+            config.player_rescan_interval_secs,
+            PLAYER_RESCAN_INTERVAL_SECS
+        );
+        assert_eq!(config.finder_timeout_secs, FINDER_TIMEOUT_SECS);
+    }
+
+    #[test]
+    fn zero_idle_clear_delay_falls_back_to_default() {
+        let mut config = Config {
+            idle_clear_delay_ms: 0,
+            ..Config::default()
+        };
+        config.fix_zero_intervals();
+
+        assert_eq!(config.idle_clear_delay_ms, IDLE_CLEAR_DELAY_MS);
+    }
+
+    #[test]
+    fn zero_track_change_debounce_falls_back_to_default() {
+        let mut config = Config {
+            track_change_debounce_ms: 0,
+            ..Config::default()
+        };
+        config.fix_zero_intervals();
+
+        assert_eq!(config.track_change_debounce_ms, TRACK_CHANGE_DEBOUNCE_MS);
+    }
+
+    #[test]
+    fn zero_progress_tick_falls_back_to_default() {
+        let mut config = Config {
+            progress_tick_ms: 0,
+            ..Config::default()
+        };
+        config.fix_zero_intervals();
+
+        assert_eq!(config.progress_tick_ms, PROGRESS_TICK_MS);
+    }
+
+    #[test]
+    fn nonzero_intervals_are_kept() {
+        let mut config = Config {
+            heartbeat_interval_secs: 7,
+            finder_timeout_secs: 1,
+            ..Config::default()
+        };
+        config.fix_zero_intervals();
+
+        assert_eq!(config.heartbeat_interval_secs, 7);
+        assert_eq!(config.finder_timeout_secs, 1);
+    }
+}
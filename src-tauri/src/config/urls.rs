@@ -0,0 +1,56 @@
+use crate::config::{self, Config};
+use crate::error::{AppError, Result};
+use reqwest::Url;
+
+/// iTunes search API URL, which may point at a mirror or proxy
+pub fn itunes_api_url() -> String {
+    config::get().itunes_api_url
+}
+
+/// Configured storefront as a lowercase country code, `None` for the global endpoints
+pub fn storefront() -> Option<String> {
+    normalize_storefront(config::get().storefront.as_deref())
+}
+
+/// Trim and lowercase a storefront, treating an empty one as unset
+fn normalize_storefront(storefront: Option<&str>) -> Option<String> {
+    storefront
+        .map(|storefront| storefront.trim().to_ascii_lowercase())
+        .filter(|storefront| !storefront.is_empty())
+}
+
+/// Check that a storefront is a two-letter country code
+fn validate_storefront(storefront: Option<&str>) -> Result<()> {
+    match normalize_storefront(storefront) {
+        Some(code) if code.len() != 2 || !code.chars().all(|c| c.is_ascii_lowercase()) => {
+            Err(AppError::Application(format!(
+                "Invalid storefront '{}': expected a two-letter country code, e.g. \"us\"",
+                code
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check that a URL is an absolute HTTP(S) URL
+pub fn validate_url(name: &str, url: &str) -> Result<()> {
+    let parsed = Url::parse(url)
+        .map_err(|e| AppError::Application(format!("Invalid {} '{}': {}", name, url, e)))?;
+
+    if !matches!(parsed.scheme(), "http" | "https") || !parsed.has_host() {
+        return Err(AppError::Application(format!(
+            "Invalid {} '{}': expected an absolute http(s) URL",
+            name, url
+        )));
+    }
+
+    Ok(())
+}
+
+/// Validate every URL of a configuration and the storefront they use
+pub fn validate(config: &Config) -> Result<()> {
+    validate_url("Apple Music URL", &config.apple_music_url)?;
+    validate_url("iTunes API URL", &config.itunes_api_url)?;
+    validate_storefront(config.storefront.as_deref())?;
+    Ok(())
+}
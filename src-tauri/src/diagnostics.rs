@@ -0,0 +1,39 @@
+use crate::apple_music::player;
+use crate::discord;
+use crate::utils::artwork;
+use serde::Serialize;
+
+/// Internal state worth pasting into a bug report
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostics {
+    pub version: String,
+    /// PID of the Apple Music browser we track
+    pub apple_music_pid: Option<u32>,
+    pub discord_connected: bool,
+    /// Whether Discord was given up on until it shows up again
+    pub discord_disabled: bool,
+    pub presence_enabled: bool,
+    /// When the presence was last sent successfully, in UNIX seconds
+    pub last_update: Option<i64>,
+    pub song_cache_hits: u64,
+    pub song_cache_misses: u64,
+    /// Requests sent to iTunes and MusicBrainz
+    pub artwork_fetches: u64,
+}
+
+/// Collect the current diagnostics
+pub fn collect() -> Diagnostics {
+    let (song_cache_hits, song_cache_misses) = player::cache_stats();
+
+    Diagnostics {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        apple_music_pid: player::get_pid().ok(),
+        discord_connected: discord::is_connected(),
+        discord_disabled: discord::is_disabled(),
+        presence_enabled: discord::is_presence_enabled(),
+        last_update: discord::last_update(),
+        song_cache_hits,
+        song_cache_misses,
+        artwork_fetches: artwork::fetch_count(),
+    }
+}
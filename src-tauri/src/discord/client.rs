@@ -1,33 +1,323 @@
-use crate::config::constants::DISCORD_CLIENT_ID;
+use crate::config::constants::{UNKNOWN_ARTIST, UNKNOWN_TRACK_TEMPLATE};
+use crate::config::{self, urls, ActivityType, TimestampMode};
+use crate::discord::sink;
+use crate::discord::template::{self, TrackText};
 use crate::error::{AppError, Result};
 use discord_rich_presence::{activity, DiscordIpc, DiscordIpcClient};
+use mpris::PlaybackStatus;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::OnceLock;
 use std::sync::{Mutex, MutexGuard};
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, trace, warn};
+
+/// Maximum length of Discord asset texts, in bytes
+const MAX_ASSET_TEXT_LEN: usize = 128;
+
+/// Maximum length of the Discord details and state lines, in bytes
+const MAX_LINE_LEN: usize = 128;
+
+/// Minimum length of the Discord details and state lines, in characters
+const MIN_LINE_CHARS: usize = 2;
+
+/// Marks a line cut short to fit Discord's limit
+const ELLIPSIS: &str = "…";
+
+/// Invisible character padding lines that are too short for Discord
+const LINE_PADDING: char = '\u{2800}';
+
+/// Maximum length of Discord button labels, in bytes
+const MAX_BUTTON_LABEL_LEN: usize = 32;
+
+/// Maximum length of Discord button URLs, in bytes
+const MAX_BUTTON_URL_LEN: usize = 512;
+
+/// Discord asset shown as the small image while paused
+const PAUSED_IMAGE_KEY: &str = "paused";
+
+/// Reconnection attempts made after the Discord IPC connection dropped
+const RECONNECT_ATTEMPTS: u32 = 3;
+
+/// Delay before the first reconnection retry, doubled after each failure, in seconds
+const RECONNECT_BASE_DELAY_SECS: u64 = 1;
+
+/// Interval between connection attempts while Discord is unreachable, in seconds
+const DISCORD_POLL_INTERVAL_SECS: u64 = 30;
+
+/// Number of IPC sockets a Discord client may listen on, `discord-ipc-0` to `discord-ipc-9`
+const IPC_SOCKET_COUNT: u32 = 10;
+
+/// Directories Discord puts its IPC socket in, relative to the runtime or temp directory
+/// Flatpak and Snap builds use a subdirectory
+const IPC_SOCKET_SUBDIRS: &[&str] = &["", "app/com.discordapp.Discord", "snap.discord"];
 
 lazy_static::lazy_static! {
     static ref DISCORD_CLIENT: Mutex<Option<DiscordIpcClient>> = Mutex::new(None);
 }
 
+// When the Apple Music session started
+static SESSION_START: OnceLock<i64> = OnceLock::new();
+
+// Set when Discord could not be reached, so presence threads stop trying
+static DISCORD_DISABLED: AtomicBool = AtomicBool::new(false);
+
+// Set once Discord was found not running, so it's only logged once
+static DISCORD_MISSING: AtomicBool = AtomicBool::new(false);
+
+// Set while a reconnection is in progress, so concurrent requests don't each start one
+static RECONNECTING: AtomicBool = AtomicBool::new(false);
+
+// When an activity was last sent successfully, in UNIX seconds, 0 before the first one
+static LAST_UPDATE: AtomicI64 = AtomicI64::new(0);
+
+// Cleared when the user paused the presence from the tray
+static PRESENCE_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Whether Discord presence is disabled because Discord could not be reached
+pub fn is_disabled() -> bool {
+    DISCORD_DISABLED.load(Ordering::SeqCst)
+}
+
+/// Whether a Discord client is connected
+pub fn is_connected() -> bool {
+    lock_client().is_ok_and(|client| client.is_some())
+}
+
+/// When an activity was last sent successfully, in UNIX seconds
+pub fn last_update() -> Option<i64> {
+    Some(LAST_UPDATE.load(Ordering::SeqCst)).filter(|time| *time > 0)
+}
+
+/// Whether the user wants the presence to be shown
+pub fn is_presence_enabled() -> bool {
+    PRESENCE_ENABLED.load(Ordering::SeqCst)
+}
+
+/// Pause or resume the presence, clearing the current activity when pausing
+pub fn set_presence_enabled(enabled: bool) -> Result<()> {
+    PRESENCE_ENABLED.store(enabled, Ordering::SeqCst);
+    info!(
+        "Discord presence {}",
+        if enabled { "resumed" } else { "paused" }
+    );
+
+    if enabled {
+        Ok(())
+    } else {
+        clear_presence()
+    }
+}
+
+/// Create a Discord client and connect it
+fn connect() -> Result<DiscordIpcClient> {
+    let mut client = DiscordIpcClient::new(&config::get().discord_client_id)?;
+
+    // Connect to Discord
+    client.connect()?;
+
+    Ok(client)
+}
+
+/// Check whether a Discord IPC socket exists, i.e. whether Discord is running at all
+fn ipc_socket_exists() -> bool {
+    let mut dirs: Vec<PathBuf> = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .filter_map(|var| std::env::var_os(var).map(PathBuf::from))
+        .collect();
+    dirs.push(PathBuf::from("/tmp"));
+
+    dirs.iter().any(|dir| {
+        IPC_SOCKET_SUBDIRS.iter().any(|subdir| {
+            (0..IPC_SOCKET_COUNT)
+                .any(|i| dir.join(subdir).join(format!("discord-ipc-{}", i)).exists())
+        })
+    })
+}
+
+/// Note that Discord isn't running, logging it only the first time
+fn discord_missing() -> AppError {
+    if !DISCORD_MISSING.swap(true, Ordering::SeqCst) {
+        info!(
+            "Discord is not running, checking again every {}s",
+            DISCORD_POLL_INTERVAL_SECS
+        );
+    }
+    AppError::Discord("Discord is not running".into())
+}
+
 /// Initialize Discord client
+/// Retries with exponential backoff, as Discord may still be starting on a cold boot
+/// After the last failed attempt Discord presence is disabled until initialized again
+/// When Discord isn't running at all, gives up right away without retrying
 pub fn initialize() -> Result<String> {
-    let mut client_guard = lock_client()?;
+    if lock_client()?.is_some() {
+        return Ok("Discord presence initialized".to_string());
+    }
 
-    if client_guard.is_none() {
-        let mut client = DiscordIpcClient::new(DISCORD_CLIENT_ID)
-            .map_err(|e| AppError::Discord(format!("Error creating Discord client: {}", e)))?;
+    // Retrying is pointless without a socket, the periodic thread checks back slowly instead
+    if !ipc_socket_exists() {
+        DISCORD_DISABLED.store(true, Ordering::SeqCst);
+        return Err(discord_missing());
+    }
+    DISCORD_MISSING.store(false, Ordering::SeqCst);
 
-        // Connect to Discord
-        client
-            .connect()
-            .map_err(|e| AppError::Discord(format!("Error connecting to Discord: {}", e)))?;
+    let config = config::get();
+    let mut delay = Duration::from_secs(config.discord_connect_retry_secs);
+    let mut attempt = 1;
+    let client = loop {
+        match connect() {
+            Ok(client) => break client,
+            Err(e) if attempt < config.discord_connect_attempts => {
+                warn!(
+                    "Discord connection attempt {}/{} failed: {}. Retrying in {}s",
+                    attempt,
+                    config.discord_connect_attempts,
+                    e,
+                    delay.as_secs()
+                );
+                attempt += 1;
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(e) => {
+                warn!(
+                    "Could not connect to Discord after {} attempts, disabling Discord presence",
+                    config.discord_connect_attempts
+                );
+                DISCORD_DISABLED.store(true, Ordering::SeqCst);
+                return Err(e);
+            }
+        }
+    };
 
-        *client_guard = Some(client);
-        println!("Discord client initialized and connected");
-    }
+    *lock_client()? = Some(client);
+    DISCORD_DISABLED.store(false, Ordering::SeqCst);
+    info!("Discord client initialized and connected");
 
     Ok("Discord presence initialized".to_string())
 }
 
+/// Truncate text to Discord's length limit without splitting a character
+pub fn clamp_text(text: &str, max_len: usize) -> &str {
+    if text.len() <= max_len {
+        return text;
+    }
+
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Hover text of the large image: the album, or the fallback text when it's unknown
+fn large_text<'a>(album: Option<&'a str>, fallback: &'a str) -> &'a str {
+    let text = album
+        .map(str::trim)
+        .filter(|album| !album.is_empty())
+        .unwrap_or(fallback);
+    clamp_text(text, MAX_ASSET_TEXT_LEN)
+}
+
+/// Whether Discord accepts the URL for a button, an invalid one gets the whole activity rejected
+fn is_valid_button_url(url: &str) -> bool {
+    url.len() <= MAX_BUTTON_URL_LEN && urls::validate_url("button URL", url).is_ok()
+}
+
+/// Label and URL of the Apple Music button, `None` when the URL isn't valid
+fn apple_music_button(apple_music_url: &str) -> Option<(String, String)> {
+    if !is_valid_button_url(apple_music_url) {
+        warn!(
+            "Leaving out the Apple Music button, invalid URL: {}",
+            apple_music_url
+        );
+        return None;
+    }
+
+    Some((
+        clamp_text(&config::get().play_button_label, MAX_BUTTON_LABEL_LEN).to_string(),
+        apple_music_url.to_string(),
+    ))
+}
+
+/// Labels and URLs of the track buttons: Apple Music first, then the configured extra one
+/// Buttons without a valid URL are left out
+fn track_buttons(track: &TrackText, apple_music_url: &str) -> Vec<(String, String)> {
+    let config = config::get();
+    let mut buttons: Vec<(String, String)> =
+        apple_music_button(apple_music_url).into_iter().collect();
+
+    if let (Some(label), Some(template)) = (&config.extra_button_label, &config.extra_button_url) {
+        let url = template
+            .replace("{title}", &urlencoding::encode(track.title))
+            .replace("{artist}", &urlencoding::encode(track.artist));
+
+        if is_valid_button_url(&url) {
+            buttons.push((clamp_text(label, MAX_BUTTON_LABEL_LEN).to_string(), url));
+        } else {
+            warn!("Leaving out the extra button, invalid URL: {}", url);
+        }
+    }
+
+    buttons
+}
+
+/// Add the buttons to an activity, Discord rejects an empty list
+fn with_buttons<'a>(
+    presence: activity::Activity<'a>,
+    buttons: &'a [(String, String)],
+) -> activity::Activity<'a> {
+    if buttons.is_empty() {
+        return presence;
+    }
+
+    presence.buttons(
+        buttons
+            .iter()
+            .map(|(label, url)| activity::Button::new(label, url))
+            .collect(),
+    )
+}
+
+/// Fit a details or state line to Discord's limits, which reject the whole activity otherwise
+/// Long lines are cut on a char boundary with an ellipsis, empty ones replaced by the placeholder
+fn sanitize_line(text: &str, placeholder: &str) -> String {
+    let text = text.trim();
+    let text = if text.is_empty() { placeholder } else { text };
+
+    let mut line = if text.len() > MAX_LINE_LEN {
+        let cut = clamp_text(text, MAX_LINE_LEN - ELLIPSIS.len()).trim_end();
+        format!("{}{}", cut, ELLIPSIS)
+    } else {
+        text.to_string()
+    };
+
+    while line.chars().count() < MIN_LINE_CHARS {
+        line.push(LINE_PADDING);
+    }
+    line
+}
+
+/// Render the details and state lines for a playback status
+fn render_lines(status: PlaybackStatus, track: &TrackText) -> (String, String) {
+    let templates = template::for_status(status, track.is_podcast);
+    (
+        sanitize_line(
+            &template::render(&templates.details, track),
+            &config::get().unknown_track_template,
+        ),
+        sanitize_line(
+            &format!(
+                "{} {}",
+                template::render(&templates.state, track),
+                track.playback_modes
+            ),
+            UNKNOWN_ARTIST,
+        ),
+    )
+}
+
 /// Lock the Discord client mutex
 pub fn lock_client() -> Result<MutexGuard<'static, Option<DiscordIpcClient>>> {
     DISCORD_CLIENT
@@ -35,156 +325,577 @@ pub fn lock_client() -> Result<MutexGuard<'static, Option<DiscordIpcClient>>> {
         .map_err(|e| AppError::Discord(format!("Failed to lock Discord client mutex: {}", e)))
 }
 
+/// Drop the current Discord client and connect a new one, e.g. after Discord was restarted
+/// Retries with exponential backoff, then disables Discord presence until the periodic thread reconnects
+/// Only one thread reconnects at a time, the others fail right away
+pub fn reconnect() -> Result<()> {
+    if RECONNECTING.swap(true, Ordering::SeqCst) {
+        return Err(AppError::Discord("Already reconnecting to Discord".into()));
+    }
+
+    let result = reconnect_with_backoff();
+    RECONNECTING.store(false, Ordering::SeqCst);
+    result
+}
+
+/// Replace the Discord client with a newly connected one, retrying with exponential backoff
+fn reconnect_with_backoff() -> Result<()> {
+    // The old client is dead, don't let other threads write to it meanwhile
+    *lock_client()? = None;
+
+    // Discord was closed, the periodic thread checks back once it's started again
+    if !ipc_socket_exists() {
+        set_disabled(true);
+        return Err(discord_missing());
+    }
+    DISCORD_MISSING.store(false, Ordering::SeqCst);
+
+    let mut delay = Duration::from_secs(RECONNECT_BASE_DELAY_SECS);
+    let mut attempt = 1;
+    let client = loop {
+        match connect() {
+            Ok(client) => break client,
+            Err(e) if attempt < RECONNECT_ATTEMPTS => {
+                warn!(
+                    "Discord reconnection attempt {}/{} failed: {}",
+                    attempt, RECONNECT_ATTEMPTS, e
+                );
+                attempt += 1;
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(e) => {
+                set_disabled(true);
+                return Err(AppError::Discord(format!(
+                    "Could not reconnect to Discord after {} attempts: {}",
+                    RECONNECT_ATTEMPTS, e
+                )));
+            }
+        }
+    };
+
+    *lock_client()? = Some(client);
+    set_disabled(false);
+    info!("Reconnected to Discord");
+
+    Ok(())
+}
+
+/// Enable or disable Discord presence, letting the tray offer a retry while it's disabled
+fn set_disabled(disabled: bool) {
+    if DISCORD_DISABLED.swap(disabled, Ordering::SeqCst) != disabled {
+        crate::ui::refresh_tray_menu();
+    }
+}
+
+/// Run a request on the Discord client, reconnecting once if the IPC connection dropped
+/// Does nothing while Discord is unreachable, the periodic thread reconnects once it's back
+pub(crate) fn send<E: std::fmt::Display>(
+    action: &str,
+    request: impl Fn(&mut DiscordIpcClient) -> std::result::Result<(), E>,
+) -> Result<()> {
+    if is_disabled() {
+        trace!("Discord is unreachable, skipped {}", action);
+        return Ok(());
+    }
+
+    let first_attempt = match lock_client()?.as_mut() {
+        Some(client) => request(client).map_err(|e| e.to_string()),
+        None => {
+            trace!("Discord client not connected, skipped {}", action);
+            return Ok(());
+        }
+    };
+
+    let Err(e) = first_attempt else {
+        return Ok(());
+    };
+
+    // Any IPC error from a connected client means the socket is gone, typically because Discord
+    // was restarted, or closed which the reconnection logs only once
+    if ipc_socket_exists() {
+        warn!("Error {}: {}. Reconnecting to Discord...", action, e);
+    } else {
+        debug!("Error {}: {}. Discord was closed", action, e);
+    }
+    reconnect()?;
+
+    match lock_client()?.as_mut() {
+        Some(client) => {
+            request(client).map_err(|e| AppError::Discord(format!("Error {}: {}", action, e)))
+        }
+        None => Err(AppError::Discord("Discord client not initialized".into())),
+    }
+}
+
 /// Clear Discord rich presence
 pub fn clear_presence() -> Result<()> {
-    let mut client_guard = lock_client()?;
+    sink::current().clear()?;
+    debug!("Discord presence cleared");
+
+    Ok(())
+}
 
-    if let Some(ref mut client) = *client_guard {
-        client
-            .clear_activity()
-            .map_err(|e| AppError::Discord(format!("Error clearing activity: {}", e)))?;
-        println!("Discord presence cleared");
+/// Record when the Apple Music session started, used by the session start timestamp mode
+pub fn mark_session_start() {
+    let _ = SESSION_START.set(unix_now());
+}
+
+/// When the Apple Music session started, defaulting to the first time it is needed
+fn session_start() -> i64 {
+    *SESSION_START.get_or_init(unix_now)
+}
+
+/// Current UNIX time in seconds
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Discord activity type matching the configured one
+fn activity_type() -> activity::ActivityType {
+    match config::get().activity_type {
+        ActivityType::Listening => activity::ActivityType::Listening,
+        ActivityType::Playing => activity::ActivityType::Playing,
     }
+}
 
-    Ok(())
+/// Build timestamps following the track position
+fn track_timestamps(start_time: i64, end_time: Option<i64>) -> activity::Timestamps {
+    let config = config::get();
+    let timestamps = activity::Timestamps::new().start(start_time);
+
+    // Ensure end time is reasonable: greater than start time and less than 24 hours
+    match end_time {
+        Some(end) if end > start_time && (end - start_time) <= 86400 => {
+            trace!(
+                "Using actual song duration for Discord presence: {} seconds",
+                end - start_time
+            );
+            return timestamps.end(end);
+        }
+        Some(_) => warn!("Received invalid end time"),
+        None => trace!("No end time available yet"),
+    }
+
+    // Without a known length, either assume the default duration or only show the elapsed time
+    if config.show_progress_when_unknown {
+        trace!(
+            "Using default duration of {} seconds",
+            config.default_track_secs
+        );
+        timestamps.end(start_time + config.default_track_secs as i64)
+    } else {
+        timestamps
+    }
 }
 
 /// Updates the Discord presence without clearing it first, preventing "flashing"
 pub fn set_activity(
-    title: &str,
-    artist: &str,
+    track: &TrackText,
     artwork_url: Option<&str>,
     start_time: i64,
     end_time: Option<i64>,
     apple_music_url: &str,
 ) -> Result<()> {
-    let mut client_guard = lock_client()?;
+    let config = config::get();
 
-    if let Some(ref mut client) = *client_guard {
-        // Assets for Discord activity
-        let mut assets = activity::Assets::new()
-            .small_image("amusic_lg")
-            .small_text("Apple Music");
+    // Assets for Discord activity
+    let mut assets = activity::Assets::new()
+        .small_image(&config.small_image_key)
+        .small_text(clamp_text(&config.small_text, MAX_ASSET_TEXT_LEN));
 
-        // Add artwork if available
-        if let Some(url) = artwork_url {
-            assets = assets.large_image(url);
-        } else {
-            assets = assets.large_image("amusic_lg");
-        }
+    // Add artwork if available
+    if let Some(url) = artwork_url {
+        assets = assets.large_image(url);
+    } else {
+        assets = assets.large_image(&config.large_image_fallback_key);
+    }
 
-        // Create button for Apple Music
-        let button = activity::Button::new("Play in Apple Music", apple_music_url);
-
-        // Create timestamps with start time and default duration of 3 minutes
-        const MINUTES_IN_SECONDS: i64 = 180; // 3 minutes
-        let mut timestamps = activity::Timestamps::new()
-            .start(start_time)
-            .end(start_time + MINUTES_IN_SECONDS);
-
-        // Only add end time if we have a valid one
-        if let Some(end) = end_time {
-            // Ensure end time is reasonable: greater than start time and less than 24 hours
-            if end > start_time && (end - start_time) <= 86400 {
-                // Calculate duration in seconds
-                let duration = end - start_time;
-
-                // Update the end time
-                timestamps = timestamps.end(start_time + duration);
-                println!(
-                    "Using actual song duration for Discord presence: {} seconds",
-                    duration
-                );
-            } else {
-                println!("Received invalid end time, using default duration of 3 minutes");
-            }
-        } else {
-            println!("No end time available yet, using default duration of 3 minutes");
-        }
+    // Show the album when hovering the artwork
+    assets = assets.large_text(large_text(track.album, &config.small_text));
 
-        // Update Discord activity
-        client
-            .set_activity(
-                activity::Activity::new()
-                    .details(title)
-                    .state(artist)
-                    .assets(assets)
-                    .activity_type(activity::ActivityType::Listening)
-                    .buttons(vec![button])
-                    .timestamps(timestamps),
-            )
-            .map_err(|e| AppError::Discord(format!("Error setting presence: {}", e)))?;
-
-        println!("Discord presence updated: {} - {}", artist, title);
-    } else {
-        return Err(AppError::Discord("Discord client not initialized".into()));
+    // Apple Music button, followed by the configured extra one
+    let buttons = track_buttons(track, apple_music_url);
+
+    // Update Discord activity
+    let (details, state) = render_lines(PlaybackStatus::Playing, track);
+    let presence = activity::Activity::new()
+        .details(&details)
+        .state(&state)
+        .assets(assets)
+        .activity_type(activity_type());
+    let mut presence = with_buttons(presence, &buttons);
+
+    // Timestamps depend on the configured mode
+    match config.timestamp_mode {
+        TimestampMode::Remaining => {
+            presence = presence.timestamps(track_timestamps(start_time, end_time));
+        }
+        TimestampMode::Elapsed => {
+            presence = presence.timestamps(activity::Timestamps::new().start(start_time));
+        }
+        TimestampMode::SessionStart => {
+            presence = presence.timestamps(activity::Timestamps::new().start(session_start()));
+        }
+        TimestampMode::None => {}
     }
 
+    sink::current().set(presence)?;
+    LAST_UPDATE.store(unix_now(), Ordering::SeqCst);
+
+    info!(
+        "Discord presence updated: {} - {}",
+        track.artist, track.title
+    );
+
     Ok(())
 }
 
-/// Schedule periodic updates for Discord presence
+/// Shows the track as paused, without timestamps so the elapsed time stops advancing
+pub fn set_paused_activity(
+    track: &TrackText,
+    artwork_url: Option<&str>,
+    elapsed_secs: i64,
+    apple_music_url: &str,
+) -> Result<()> {
+    let config = config::get();
+
+    // Show the frozen position next to the small image
+    let paused_text = format!("Paused at {}:{:02}", elapsed_secs / 60, elapsed_secs % 60);
+
+    let assets = activity::Assets::new()
+        .large_image(artwork_url.unwrap_or(&config.large_image_fallback_key))
+        .large_text(large_text(track.album, &config.small_text))
+        .small_image(PAUSED_IMAGE_KEY)
+        .small_text(&paused_text);
+
+    let buttons = track_buttons(track, apple_music_url);
+
+    let (details, state) = render_lines(PlaybackStatus::Paused, track);
+    let presence = activity::Activity::new()
+        .details(&details)
+        .state(&state)
+        .assets(assets)
+        .activity_type(activity_type());
+    let presence = with_buttons(presence, &buttons);
+
+    sink::current().set(presence)?;
+    LAST_UPDATE.store(unix_now(), Ordering::SeqCst);
+
+    info!(
+        "Discord presence paused: {} - {}",
+        track.artist, track.title
+    );
+
+    Ok(())
+}
+
+/// Shows a placeholder when the player doesn't report any track metadata
+pub fn set_placeholder_activity(text: &str, start_time: i64) -> Result<()> {
+    let config = config::get();
+
+    let assets = activity::Assets::new()
+        .large_image(&config.large_image_fallback_key)
+        .small_image(&config.small_image_key)
+        .small_text(clamp_text(&config.small_text, MAX_ASSET_TEXT_LEN));
+
+    // Without a track to search for, the button opens Apple Music itself
+    let buttons: Vec<(String, String)> = apple_music_button(&config.apple_music_url)
+        .into_iter()
+        .collect();
+
+    let details = sanitize_line(text, UNKNOWN_TRACK_TEMPLATE);
+    let presence = activity::Activity::new()
+        .details(&details)
+        .assets(assets)
+        .activity_type(activity_type())
+        .timestamps(activity::Timestamps::new().start(start_time));
+    let presence = with_buttons(presence, &buttons);
+
+    sink::current().set(presence)?;
+    LAST_UPDATE.store(unix_now(), Ordering::SeqCst);
+
+    info!("Discord presence updated with placeholder: {}", text);
+
+    Ok(())
+}
+
+/// Schedule lightweight progress refreshes for Discord presence
+/// Track changes, seeks and play/pause are handled by the MPRIS event listener,
+/// this only keeps the progress bar in sync between events and reconnects to Discord when needed
 pub fn start_periodic_updates() {
     std::thread::spawn(|| {
-        // Wait a bit before starting updates
-        std::thread::sleep(Duration::from_secs(5));
-        println!("Starting Discord presence polling thread");
-
-        // Track how many times we've tried to update for the current song
-        let mut attempts_for_current_song = 0;
-        let mut last_song_title = String::new();
-        let mut last_song_artist = String::new();
+        debug!("Starting Discord presence progress thread");
+        let mut last_connect_attempt = Instant::now();
 
         loop {
-            match crate::apple_music::player::update_discord_presence() {
-                Ok(msg) => {
-                    println!("Polling update: {}", msg);
-
-                    // Check if we're waiting for song data
-                    if msg.contains("Waiting for complete song data for") {
-                        // Extract song info from message
-                        if let Some(song_info) =
-                            msg.strip_prefix("Waiting for complete song data for ")
-                        {
-                            let parts: Vec<&str> = song_info.split(" - ").collect();
-                            if parts.len() == 2 {
-                                let artist = parts[0];
-                                let title = parts[1];
-
-                                // Check if this is the same song as last time
-                                if artist == last_song_artist && title == last_song_title {
-                                    attempts_for_current_song += 1;
-                                } else {
-                                    // New song, reset counter
-                                    attempts_for_current_song = 1;
-                                    last_song_title = title.to_string();
-                                    last_song_artist = artist.to_string();
-                                }
-
-                                // For the first few attempts, poll more frequently to get data quickly
-                                if attempts_for_current_song < 5 {
-                                    std::thread::sleep(Duration::from_secs(2));
-                                    continue;
-                                }
-                            }
-                        }
-                    } else {
-                        // We got complete data or something else happened
-                        attempts_for_current_song = 0;
-                        last_song_title.clear();
-                        last_song_artist.clear();
-                    }
+            // Check back sooner while the player hasn't reported the song length yet
+            let config = config::get();
+            let mut interval = Duration::from_secs(config.poll_interval_secs);
+
+            // Keep trying to reach Discord, e.g. when it was started after amusic
+            let connect_due =
+                last_connect_attempt.elapsed() >= Duration::from_secs(DISCORD_POLL_INTERVAL_SECS);
+            if is_disabled() && connect_due {
+                last_connect_attempt = Instant::now();
+                if initialize().is_ok() {
+                    info!("Discord became available, restoring presence");
+                    crate::ui::refresh_tray_menu();
+                    let _ = crate::apple_music::update_discord_presence();
                 }
-                Err(e) => {
-                    // Only show errors that aren't expected during initialization
-                    if !e.to_string().contains("PID not stored")
-                        && !e.to_string().contains("not found")
-                    {
-                        println!("Polling update error: {}", e);
-                    }
+            }
+
+            // Don't refresh while Discord is unreachable or the presence is paused
+            if !is_disabled() && is_presence_enabled() {
+                match crate::apple_music::player::refresh_progress() {
+                    Ok(true) => interval = Duration::from_secs(config.fast_retry_secs),
+                    Ok(false) => {}
+                    Err(e) => trace!("Progress refresh skipped: {}", e),
                 }
             }
-            std::thread::sleep(Duration::from_secs(10)); // Standard interval
+
+            std::thread::sleep(interval);
         }
     });
 }
+
+/// Periodically re-assert the current activity so Discord doesn't drop it during long tracks
+pub fn start_heartbeat() {
+    std::thread::spawn(|| {
+        debug!("Starting Discord presence heartbeat thread");
+
+        loop {
+            std::thread::sleep(Duration::from_secs(config::get().heartbeat_interval_secs));
+            if is_disabled() || !is_presence_enabled() {
+                continue;
+            }
+
+            // Nothing to re-assert while paused, stopped or without a player
+            match crate::apple_music::player::reassert_presence() {
+                Ok(()) => trace!("Heartbeat: Discord presence re-asserted"),
+                Err(e) => trace!("Heartbeat skipped: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::discord::sink::RecordingSink;
+    use crate::testing;
+    use serde_json::json;
+
+    const SEARCH_URL: &str = "https://music.apple.com/search?term=Title%20Artist";
+
+    /// The only activity recorded since the sink was started
+    fn recorded_activity() -> serde_json::Value {
+        let mut recorded = RecordingSink::take();
+        assert_eq!(recorded.len(), 1, "expected a single presence update");
+        recorded
+            .remove(0)
+            .expect("expected an activity, not a clear")
+    }
+
+    fn track() -> TrackText<'static> {
+        TrackText {
+            title: "Title",
+            artist: "Artist",
+            album: Some("Album"),
+            ..TrackText::default()
+        }
+    }
+
+    #[test]
+    fn button_urls_must_be_absolute_and_short() {
+        assert!(is_valid_button_url(
+            "https://music.apple.com/search?term=Song"
+        ));
+        assert!(!is_valid_button_url("music.apple.com/search"));
+        assert!(!is_valid_button_url("javascript:alert(1)"));
+
+        let long_url = format!("https://music.apple.com/search?term={}", "a".repeat(512));
+        assert!(!is_valid_button_url(&long_url));
+    }
+
+    #[test]
+    fn long_lines_are_cut_with_an_ellipsis() {
+        let line = sanitize_line(&"a".repeat(300), UNKNOWN_ARTIST);
+
+        assert!(line.len() <= MAX_LINE_LEN);
+        assert!(line.ends_with(ELLIPSIS));
+        assert_eq!(line, format!("{}{}", "a".repeat(125), ELLIPSIS));
+    }
+
+    #[test]
+    fn lines_are_cut_on_a_char_boundary() {
+        // Byte 128 falls in the middle of a two-byte "é" and of a four-byte emoji
+        for title in ["aé".repeat(100), format!("ab{}", "🎵".repeat(40))] {
+            assert!(!title.is_char_boundary(MAX_LINE_LEN));
+            assert!(clamp_text(&title, MAX_LINE_LEN).len() < MAX_LINE_LEN);
+
+            let line = sanitize_line(&title, UNKNOWN_ARTIST);
+            assert!(line.len() <= MAX_LINE_LEN);
+            assert!(line.ends_with(ELLIPSIS));
+            assert!(title.starts_with(line.trim_end_matches(ELLIPSIS)));
+        }
+
+        assert_eq!(clamp_text("éé", 3), "é");
+        assert_eq!(clamp_text("short", MAX_ASSET_TEXT_LEN), "short");
+    }
+
+    #[test]
+    fn empty_lines_get_the_placeholder() {
+        assert_eq!(sanitize_line("", UNKNOWN_ARTIST), UNKNOWN_ARTIST);
+        assert_eq!(sanitize_line("   ", UNKNOWN_ARTIST), UNKNOWN_ARTIST);
+
+        // Discord rejects lines shorter than two characters
+        assert_eq!(sanitize_line("a", UNKNOWN_ARTIST), "a\u{2800}");
+    }
+
+    #[test]
+    fn playing_activity() {
+        let _state = testing::with_config(Config::default());
+        RecordingSink::start();
+
+        set_activity(
+            &track(),
+            Some("https://example.com/cover.jpg"),
+            1_000,
+            Some(1_200),
+            SEARCH_URL,
+        )
+        .unwrap();
+
+        assert_eq!(
+            recorded_activity(),
+            json!({
+                "details": "Title",
+                "state": "Artist",
+                "type": 2,
+                "timestamps": { "start": 1_000, "end": 1_200 },
+                "assets": {
+                    "large_image": "https://example.com/cover.jpg",
+                    "large_text": "Album",
+                    "small_image": "amusic_lg",
+                    "small_text": "Apple Music",
+                },
+                "buttons": [{ "label": "Play in Apple Music", "url": SEARCH_URL }],
+            })
+        );
+    }
+
+    #[test]
+    fn paused_activity() {
+        let _state = testing::with_config(Config {
+            paused_details_template: Some("⏸ {title}".to_string()),
+            ..Config::default()
+        });
+        RecordingSink::start();
+
+        set_paused_activity(&track(), None, 65, SEARCH_URL).unwrap();
+
+        // No timestamps, so the elapsed time stops advancing
+        assert_eq!(
+            recorded_activity(),
+            json!({
+                "details": "⏸ Title",
+                "state": "Artist",
+                "type": 2,
+                "assets": {
+                    "large_image": "amusic_lg",
+                    "large_text": "Album",
+                    "small_image": "paused",
+                    "small_text": "Paused at 1:05",
+                },
+                "buttons": [{ "label": "Play in Apple Music", "url": SEARCH_URL }],
+            })
+        );
+    }
+
+    #[test]
+    fn placeholder_activity() {
+        let _state = testing::with_config(Config {
+            activity_type: ActivityType::Playing,
+            ..Config::default()
+        });
+        RecordingSink::start();
+
+        set_placeholder_activity("", 1_000).unwrap();
+
+        assert_eq!(
+            recorded_activity(),
+            json!({
+                "details": UNKNOWN_TRACK_TEMPLATE,
+                "type": 0,
+                "timestamps": { "start": 1_000 },
+                "assets": {
+                    "large_image": "amusic_lg",
+                    "small_image": "amusic_lg",
+                    "small_text": "Apple Music",
+                },
+                "buttons": [{ "label": "Play in Apple Music", "url": "https://music.apple.com" }],
+            })
+        );
+    }
+
+    #[test]
+    fn placeholder_activity_leaves_out_an_invalid_button() {
+        let _state = testing::with_config(Config {
+            apple_music_url: format!("https://music.apple.com/{}", "a".repeat(512)),
+            ..Config::default()
+        });
+        RecordingSink::start();
+
+        set_placeholder_activity("Loading", 1_000).unwrap();
+
+        let activity = recorded_activity();
+        assert_eq!(activity["details"], "Loading");
+        assert!(activity.get("buttons").is_none());
+    }
+
+    #[test]
+    fn clear_is_recorded() {
+        let _state = testing::with_config(Config::default());
+        RecordingSink::start();
+
+        clear_presence().unwrap();
+
+        assert_eq!(RecordingSink::take(), [None]);
+    }
+
+    #[test]
+    fn nothing_is_sent_without_a_discord_client() {
+        let _state = testing::with_config(Config::default());
+        let request = |_: &mut DiscordIpcClient| -> std::result::Result<(), String> {
+            panic!("no client to send to")
+        };
+
+        // Not connected yet, no reconnection attempts blocking the caller
+        assert!(send("testing", request).is_ok());
+
+        // Discord unreachable, the periodic thread takes care of reconnecting
+        DISCORD_DISABLED.store(true, Ordering::SeqCst);
+        let result = send("testing", request);
+        DISCORD_DISABLED.store(false, Ordering::SeqCst);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn only_one_reconnection_runs_at_a_time() {
+        let _state = testing::with_config(Config::default());
+
+        // Another thread is reconnecting, this one doesn't wait through a second backoff
+        RECONNECTING.store(true, Ordering::SeqCst);
+        let started = Instant::now();
+        let result = reconnect();
+        RECONNECTING.store(false, Ordering::SeqCst);
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(RECONNECT_BASE_DELAY_SECS));
+    }
+}
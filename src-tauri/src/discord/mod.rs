@@ -1,4 +1,12 @@
 pub mod client;
+pub mod sink;
+pub mod template;
 
 // Re-export commonly used functions
-pub use client::{clear_presence, initialize, set_activity, start_periodic_updates};
+pub use client::{
+    clear_presence, initialize, is_connected, is_disabled, is_presence_enabled, last_update,
+    mark_session_start, reconnect, set_activity, set_paused_activity, set_placeholder_activity,
+    set_presence_enabled, start_heartbeat, start_periodic_updates,
+};
+pub use sink::PresenceSink;
+pub use template::TrackText;
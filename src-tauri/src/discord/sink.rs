@@ -0,0 +1,97 @@
+use crate::discord::client;
+use crate::error::Result;
+use discord_rich_presence::{activity::Activity, DiscordIpc};
+use std::sync::OnceLock;
+use tracing::debug;
+
+/// Destination of the built presence
+/// The Discord IPC client in the app, a fake capturing the activities can stand in for it
+pub trait PresenceSink: Send + Sync {
+    /// Show an activity
+    fn set(&self, activity: Activity<'_>) -> Result<()>;
+
+    /// Remove the current activity
+    fn clear(&self) -> Result<()>;
+}
+
+/// Sends the presence to Discord over IPC, reconnecting once when the connection dropped
+/// Presence updates are dropped while Discord is unreachable
+pub struct IpcSink;
+
+impl PresenceSink for IpcSink {
+    fn set(&self, activity: Activity<'_>) -> Result<()> {
+        client::send("setting presence", |client| {
+            client.set_activity(activity.clone())
+        })
+    }
+
+    fn clear(&self) -> Result<()> {
+        // Nothing to clear while Discord isn't connected
+        client::send("clearing activity", |client| client.clear_activity())
+    }
+}
+
+// Sink the presence goes to, the IPC client unless another one was installed first
+static SINK: OnceLock<Box<dyn PresenceSink>> = OnceLock::new();
+
+/// Install the sink the presence goes to, before the first presence update
+/// Returns false when a sink is already in use
+pub fn install(sink: Box<dyn PresenceSink>) -> bool {
+    let installed = SINK.set(sink).is_ok();
+    if !installed {
+        debug!("A presence sink is already in use");
+    }
+    installed
+}
+
+/// Sink the presence goes to
+pub fn current() -> &'static dyn PresenceSink {
+    SINK.get_or_init(|| Box::new(IpcSink)).as_ref()
+}
+
+// Activities sent to the recording sink as Discord receives them, `None` for a clear
+#[cfg(test)]
+static RECORDED: std::sync::Mutex<Vec<Option<serde_json::Value>>> =
+    std::sync::Mutex::new(Vec::new());
+
+/// Records the presence instead of sending it, for tests
+#[cfg(test)]
+pub struct RecordingSink;
+
+#[cfg(test)]
+impl RecordingSink {
+    /// Install the recording sink, forgetting what it recorded so far
+    /// Tests sending a presence hold the global state, so they never record at the same time
+    pub fn start() {
+        install(Box::new(RecordingSink));
+        Self::take();
+    }
+
+    /// Everything recorded since the last call, oldest first
+    pub fn take() -> Vec<Option<serde_json::Value>> {
+        let mut recorded = RECORDED
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        std::mem::take(&mut *recorded)
+    }
+}
+
+#[cfg(test)]
+impl PresenceSink for RecordingSink {
+    fn set(&self, activity: Activity<'_>) -> Result<()> {
+        let activity = serde_json::to_value(&activity)?;
+        RECORDED
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(Some(activity));
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        RECORDED
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(None);
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use crate::config;
+use mpris::PlaybackStatus;
+
+/// Details and state templates of a presence
+#[derive(Clone, Debug)]
+pub struct Templates {
+    pub details: String,
+    pub state: String,
+}
+
+/// Select the templates for a playback status, falling back to the base templates
+/// Podcast episodes use the podcast templates whatever the status
+pub fn for_status(status: PlaybackStatus, is_podcast: bool) -> Templates {
+    let config = config::get();
+
+    if is_podcast {
+        return Templates {
+            details: config.podcast_details_template,
+            state: config.podcast_state_template,
+        };
+    }
+
+    match status {
+        PlaybackStatus::Paused => Templates {
+            details: config
+                .paused_details_template
+                .unwrap_or(config.details_template),
+            state: config
+                .paused_state_template
+                .unwrap_or(config.state_template),
+        },
+        _ => Templates {
+            details: config.details_template,
+            state: config.state_template,
+        },
+    }
+}
+
+/// Track text the templates can refer to
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TrackText<'a> {
+    pub title: &'a str,
+    pub artist: &'a str,
+    pub album: Option<&'a str>,
+    pub album_artist: Option<&'a str>,
+    /// Shuffle and repeat glyphs appended to the state line, empty for none
+    pub playback_modes: &'a str,
+    /// Rendered with the podcast templates, the title being the episode and the artist the show
+    pub is_podcast: bool,
+}
+
+impl TrackText<'_> {
+    /// Value of a template token, `None` for unknown tokens
+    fn token(&self, name: &str) -> Option<&str> {
+        match name {
+            "title" | "episode" => Some(self.title),
+            "artist" | "show" => Some(self.artist),
+            "album" => Some(self.album.unwrap_or_default()),
+            "album_artist" => Some(self.album_artist.unwrap_or_default()),
+            _ => None,
+        }
+    }
+}
+
+/// Render a template, replacing the {title}, {artist}, {album} and {album_artist} tokens,
+/// and {episode} and {show}, the names podcast templates use for the title and artist
+/// A token that resolves to empty is dropped along with the text joining it to the
+/// previous token, so "{title} by {artist}" never renders as "Title by "
+pub fn render(template: &str, track: &TrackText) -> String {
+    // Split the template into the literal texts around the known tokens
+    let mut texts = Vec::new();
+    let mut values = Vec::new();
+    let mut literal = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        literal.push_str(&rest[..start]);
+
+        // Unknown tokens are kept as written
+        let token = &rest[start..=start + len];
+        match track.token(&token[1..token.len() - 1]) {
+            Some(value) => {
+                texts.push(std::mem::take(&mut literal));
+                values.push(value.trim());
+            }
+            None => literal.push_str(token),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    literal.push_str(rest);
+
+    let mut line = String::new();
+    let mut has_value = false;
+    for (index, (text, value)) in texts.iter().zip(&values).enumerate() {
+        if value.is_empty() {
+            continue;
+        }
+
+        // Text after a dropped token joined it to this one, so it goes too
+        if index == 0 || has_value {
+            line.push_str(text);
+        }
+        line.push_str(value);
+        has_value = true;
+    }
+
+    // Trailing text belongs to the last token
+    if values.last().is_none_or(|value| !value.is_empty()) {
+        line.push_str(&literal);
+    }
+
+    line.trim().to_string()
+}
@@ -1,33 +1,49 @@
-use std::fmt;
+use thiserror::Error;
 
 /// Custom error type for the application
-#[derive(Debug)]
+#[derive(Debug, Error)]
 pub enum AppError {
     /// Discord-related errors
+    #[error("Discord error: {0}")]
     Discord(String),
     /// MPRIS-related errors
+    #[error("MPRIS error: {0}")]
     Mpris(String),
     /// Player-related errors
+    #[error("Player error: {0}")]
     Player(String),
     /// Network-related errors
+    #[error("Network error: {0}")]
     Network(String),
     /// General application errors
+    #[error("Application error: {0}")]
     Application(String),
+    /// None of the configured browsers is installed
+    #[error("No compatible browser found. Please install Chromium, Brave, Google Chrome, Vivaldi or Firefox.")]
+    NoBrowser,
+    /// D-Bus errors talking to an MPRIS player
+    #[error("MPRIS error: {0}")]
+    DBus(#[from] mpris::DBusError),
+    /// Errors looking up MPRIS players
+    #[error("MPRIS error: {0}")]
+    Finding(#[from] mpris::FindingError),
+    /// HTTP errors
+    #[error("Network error: {0}")]
+    Http(#[from] reqwest::Error),
+    /// File system errors
+    #[error("Application error: {0}")]
+    Io(#[from] std::io::Error),
+    /// JSON (de)serialization errors
+    #[error("Application error: {0}")]
+    Json(#[from] serde_json::Error),
 }
 
-impl fmt::Display for AppError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            AppError::Discord(msg) => write!(f, "Discord error: {}", msg),
-            AppError::Mpris(msg) => write!(f, "MPRIS error: {}", msg),
-            AppError::Player(msg) => write!(f, "Player error: {}", msg),
-            AppError::Network(msg) => write!(f, "Network error: {}", msg),
-            AppError::Application(msg) => write!(f, "Application error: {}", msg),
-        }
+// The Discord IPC client reports untyped boxed errors, which aren't Send
+impl From<Box<dyn std::error::Error>> for AppError {
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        AppError::Discord(e.to_string())
     }
 }
 
-impl std::error::Error for AppError {}
-
 /// Standard Result type for the application
 pub type Result<T> = std::result::Result<T, AppError>;
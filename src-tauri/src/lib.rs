@@ -1,26 +1,140 @@
 pub mod apple_music;
+pub mod cli;
 pub mod commands;
 pub mod config;
+pub mod diagnostics;
 pub mod discord;
 pub mod error;
+pub mod scrobble;
 pub mod ui;
 pub mod utils;
 
+#[cfg(test)]
+mod testing;
+
+use config::LogFormat;
+use tracing::{error, warn};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// Build a subscriber logging to stderr, honoring RUST_LOG and defaulting to info for amusic
+fn subscriber(format: LogFormat) -> Box<dyn tracing::Subscriber + Send + Sync> {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("warn,amusic_lib=info"));
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+
+    match format {
+        LogFormat::Pretty => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(builder.json().finish()),
+    }
+}
+
+/// Initialize logging in the configured format
+fn init_logging(format: LogFormat) {
+    subscriber(format).init();
+}
+
 /// Main entry point for the application
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Answer --version and --help before anything else, even with a broken config
+    let action = cli::parse_args(std::env::args());
+    match action {
+        cli::CliAction::Version => {
+            println!("{}", cli::version());
+            return;
+        }
+        cli::CliAction::Help => {
+            println!("{}", cli::usage());
+            return;
+        }
+        _ => {}
+    }
+
+    // The log format comes from the config file, so it's loaded with the default format
+    let loaded =
+        tracing::subscriber::with_default(subscriber(LogFormat::Pretty), config::load_config);
+    init_logging(
+        loaded
+            .as_ref()
+            .map_or(LogFormat::Pretty, |config| config.log_format),
+    );
+
+    // Refuse to start with a configuration that would make every lookup fail silently
+    match loaded {
+        Ok(config) => config::set(config),
+        Err(e) => {
+            error!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Handle one-shot invocations and headless mode without starting the tray stack
+    match action {
+        cli::CliAction::NowPlaying => std::process::exit(cli::print_now_playing()),
+        cli::CliAction::Headless => std::process::exit(cli::run_headless()),
+        cli::CliAction::Run | cli::CliAction::Version | cli::CliAction::Help => {}
+    }
+
+    // Clean up like the tray's Quit item on Ctrl+C or logout
+    // The signals are blocked before Tauri starts its threads, so only the waiting thread gets them
+    match cli::block_shutdown_signals() {
+        Ok(signals) => {
+            std::thread::spawn(move || {
+                cli::wait_for_shutdown(&signals);
+                match ui::tray::app_handle() {
+                    Some(app) => app.exit(0),
+                    None => std::process::exit(0),
+                }
+            });
+        }
+        Err(e) => warn!("Failed to block shutdown signals: {}", e),
+    }
+
     // Initialize the Tauri application
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![commands::start_discord_presence])
+        .plugin(tauri_plugin_dialog::init())
+        .invoke_handler(tauri::generate_handler![
+            commands::start_discord_presence,
+            commands::retry_discord,
+            commands::refresh_presence,
+            commands::get_volume,
+            commands::set_volume,
+            commands::export_history,
+            commands::clear_history,
+            commands::get_play_history,
+            commands::ensure_apple_music_open,
+            commands::get_current_track,
+            commands::set_presence_enabled,
+            commands::restart_apple_music,
+            commands::get_config,
+            commands::set_config,
+            commands::list_mpris_players,
+            commands::get_diagnostics
+        ])
         .setup(|app| {
             // Setup the tray icon
             if let Err(e) = ui::setup_tray(app) {
-                eprintln!("Failed to setup tray: {}", e);
+                error!("Failed to setup tray: {}", e);
             }
 
-            // Open Apple Music on startup
-            apple_music::open_apple_music();
+            if config::get().auto_launch {
+                // Open Apple Music on startup, staying in the tray so it can be opened later
+                if let Err(e) = apple_music::open_apple_music() {
+                    ui::report_launch_error(&e);
+                }
+            } else {
+                // Wait for Apple Music to be opened manually
+                std::thread::spawn(|| {
+                    if let Err(e) = commands::start_discord_presence() {
+                        error!("{}", e);
+                    }
+                });
+            }
 
             Ok(())
         })
@@ -0,0 +1,106 @@
+use crate::config::Config;
+use crate::error::{AppError, Result};
+use crate::scrobble::ScrobbleTrack;
+use crate::utils::http;
+
+/// Last.fm API endpoint
+const API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+
+/// Last.fm client authenticated with an API key, secret and session key
+#[derive(Clone, Debug)]
+pub struct LastFm {
+    api_key: String,
+    api_secret: String,
+    session_key: String,
+}
+
+impl LastFm {
+    /// Build a client from the configured credentials, if they are all set
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let non_empty = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        Some(LastFm {
+            api_key: non_empty(&config.lastfm_api_key)?,
+            api_secret: non_empty(&config.lastfm_api_secret)?,
+            session_key: non_empty(&config.lastfm_session_key)?,
+        })
+    }
+
+    /// Sign the call parameters: sorted key/value pairs followed by the secret, hashed with MD5
+    fn sign(&self, params: &[(&str, String)]) -> String {
+        let mut sorted: Vec<&(&str, String)> = params.iter().collect();
+        sorted.sort_by_key(|(key, _)| *key);
+
+        let mut payload: String = sorted
+            .iter()
+            .map(|(key, value)| format!("{}{}", key, value))
+            .collect();
+        payload.push_str(&self.api_secret);
+
+        format!("{:x}", md5::compute(payload))
+    }
+
+    /// Make a signed write call to the Last.fm API
+    fn call(&self, method: &str, mut params: Vec<(&str, String)>) -> Result<()> {
+        params.push(("method", method.to_string()));
+        params.push(("api_key", self.api_key.clone()));
+        params.push(("sk", self.session_key.clone()));
+        let signature = self.sign(&params);
+        params.push(("api_sig", signature));
+        // Not part of the signature
+        params.push(("format", "json".to_string()));
+
+        let response = http::client()
+            .post(API_URL)
+            .form(&params)
+            .send()
+            .map_err(|e| AppError::Network(format!("Error calling Last.fm {}: {}", method, e)))?;
+
+        let json = response.json::<serde_json::Value>()?;
+
+        // Errors come back as {"error": code, "message": "..."}
+        if let Some(code) = json["error"].as_i64() {
+            return Err(AppError::Network(format!(
+                "Last.fm {} failed ({}): {}",
+                method,
+                code,
+                json["message"].as_str().unwrap_or("unknown error")
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Common track parameters
+    fn track_params(track: &ScrobbleTrack) -> Vec<(&'static str, String)> {
+        let mut params = vec![
+            ("artist", track.artist.clone()),
+            ("track", track.title.clone()),
+        ];
+        if let Some(album) = &track.album {
+            params.push(("album", album.clone()));
+        }
+        if let Some(length) = track.length_secs {
+            params.push(("duration", length.to_string()));
+        }
+        params
+    }
+
+    /// Tell Last.fm which track just started
+    pub fn update_now_playing(&self, track: &ScrobbleTrack) -> Result<()> {
+        self.call("track.updateNowPlaying", Self::track_params(track))
+    }
+
+    /// Scrobble a track that was listened to long enough
+    pub fn scrobble(&self, track: &ScrobbleTrack) -> Result<()> {
+        let mut params = Self::track_params(track);
+        params.push(("timestamp", track.started_at.to_string()));
+        self.call("track.scrobble", params)
+    }
+}
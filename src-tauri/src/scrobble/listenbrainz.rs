@@ -0,0 +1,87 @@
+use crate::config::Config;
+use crate::error::{AppError, Result};
+use crate::scrobble::ScrobbleTrack;
+use crate::utils::http;
+use serde_json::json;
+
+/// ListenBrainz listen submission endpoint
+const SUBMIT_URL: &str = "https://api.listenbrainz.org/1/submit-listens";
+
+/// ListenBrainz client authenticated with a user token
+#[derive(Clone, Debug)]
+pub struct ListenBrainz {
+    token: String,
+}
+
+impl ListenBrainz {
+    /// Build a client from the configured user token, if it is set
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let token = config.listenbrainz_token.as_deref()?.trim();
+        if token.is_empty() {
+            return None;
+        }
+
+        Some(ListenBrainz {
+            token: token.to_string(),
+        })
+    }
+
+    /// Metadata of a track, as expected in a listen payload
+    fn track_metadata(track: &ScrobbleTrack) -> serde_json::Value {
+        let mut metadata = json!({
+            "artist_name": track.artist,
+            "track_name": track.title,
+        });
+        if let Some(album) = &track.album {
+            metadata["release_name"] = json!(album);
+        }
+        if let Some(length) = track.length_secs {
+            metadata["additional_info"] = json!({ "duration_ms": length * 1000 });
+        }
+        metadata
+    }
+
+    /// Submit a listen payload
+    fn submit(&self, listen_type: &str, listen: serde_json::Value) -> Result<()> {
+        let body = json!({
+            "listen_type": listen_type,
+            "payload": [listen],
+        });
+
+        let response = http::client()
+            .post(SUBMIT_URL)
+            .header("Authorization", format!("Token {}", self.token))
+            .json(&body)
+            .send()
+            .map_err(|e| AppError::Network(format!("Error calling ListenBrainz: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(AppError::Network(format!(
+                "ListenBrainz {} submission failed: {}",
+                listen_type,
+                response.status()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Tell ListenBrainz which track just started
+    pub fn update_now_playing(&self, track: &ScrobbleTrack) -> Result<()> {
+        self.submit(
+            "playing_now",
+            json!({ "track_metadata": Self::track_metadata(track) }),
+        )
+    }
+
+    /// Submit a track that was listened to long enough
+    pub fn scrobble(&self, track: &ScrobbleTrack) -> Result<()> {
+        self.submit(
+            "single",
+            json!({
+                "listened_at": track.started_at,
+                "track_metadata": Self::track_metadata(track),
+            }),
+        )
+    }
+}
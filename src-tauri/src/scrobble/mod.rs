@@ -0,0 +1,257 @@
+pub mod lastfm;
+pub mod listenbrainz;
+pub mod queue;
+
+use crate::config;
+use crate::error::{AppError, Result};
+use lastfm::LastFm;
+use listenbrainz::ListenBrainz;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tracing::{debug, error, info, warn};
+
+/// Tracks shorter than this are never scrobbled, in seconds
+const MIN_TRACK_SECS: u64 = 30;
+
+/// Listening time after which a track is scrobbled regardless of its length, in seconds
+const MAX_THRESHOLD_SECS: u64 = 240;
+
+/// Track submitted to scrobbling services
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ScrobbleTrack {
+    pub title: String,
+    pub artist: String,
+    pub album: Option<String>,
+    pub length_secs: Option<u64>,
+    /// When the track started playing, in UNIX seconds
+    pub started_at: i64,
+}
+
+/// Service listens are submitted to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrobbleService {
+    LastFm,
+    ListenBrainz,
+}
+
+impl ScrobbleService {
+    /// Parse a service from its configured name
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "lastfm" => Ok(ScrobbleService::LastFm),
+            "listenbrainz" => Ok(ScrobbleService::ListenBrainz),
+            _ => Err(AppError::Application(format!(
+                "Unknown scrobble service '{}', expected one of: lastfm, listenbrainz",
+                name
+            ))),
+        }
+    }
+
+    /// Submit a track as now playing or as a listen, skipping services without credentials
+    fn submit(self, track: &ScrobbleTrack, scrobble: bool) -> Option<Result<()>> {
+        let config = config::get();
+        match self {
+            ScrobbleService::LastFm => {
+                let lastfm = LastFm::from_config(&config)?;
+                Some(if scrobble {
+                    lastfm.scrobble(track)
+                } else {
+                    lastfm.update_now_playing(track)
+                })
+            }
+            ScrobbleService::ListenBrainz => {
+                let listenbrainz = ListenBrainz::from_config(&config)?;
+                Some(if scrobble {
+                    listenbrainz.scrobble(track)
+                } else {
+                    listenbrainz.update_now_playing(track)
+                })
+            }
+        }
+    }
+}
+
+/// Parse scrobble service names
+pub fn parse_services(names: &[String]) -> Result<Vec<ScrobbleService>> {
+    names
+        .iter()
+        .map(|name| ScrobbleService::from_name(name))
+        .collect()
+}
+
+/// Track currently playing, and whether it was scrobbled already
+struct Playing {
+    track: ScrobbleTrack,
+    scrobbled: bool,
+}
+
+// Track currently playing
+lazy_static::lazy_static! {
+    static ref PLAYING: Mutex<Option<Playing>> = Mutex::new(None);
+}
+
+/// Position at which a track gets scrobbled: half its length, at most four minutes
+fn threshold_secs(length_secs: Option<u64>) -> u64 {
+    length_secs
+        .map(|length| (length / 2).min(MAX_THRESHOLD_SECS))
+        .unwrap_or(MAX_THRESHOLD_SECS)
+}
+
+/// Whether a track is due to be scrobbled at a position, never when it's too short
+fn is_due(track: &ScrobbleTrack, position_secs: u64) -> bool {
+    let too_short = track
+        .length_secs
+        .is_some_and(|length| length < MIN_TRACK_SECS);
+
+    !too_short && position_secs >= threshold_secs(track.length_secs)
+}
+
+/// Submit to the configured services on a background thread, so presence updates never wait
+fn submit(track: ScrobbleTrack, scrobble: bool) {
+    let services = match parse_services(&config::get().scrobble_services) {
+        Ok(services) => services,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    if services.is_empty() {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        for service in services {
+            match service.submit(&track, scrobble) {
+                Some(Ok(())) => {
+                    if scrobble {
+                        info!(
+                            "Scrobbled {} - {} to {:?}",
+                            track.artist, track.title, service
+                        );
+                    } else {
+                        debug!(
+                            "Now playing sent to {:?}: {} - {}",
+                            service, track.artist, track.title
+                        );
+                    }
+
+                    // The service is reachable again, catch up on listens that failed earlier
+                    queue::retry(service);
+                }
+                Some(Err(e)) => {
+                    warn!("{}", e);
+
+                    // Listens must not be lost while offline, now playing is only worth it live
+                    if scrobble {
+                        queue::push(service, track.clone());
+                    }
+                }
+                // No credentials configured for this service
+                None => {}
+            }
+        }
+    });
+}
+
+/// Record that a new track started playing and announce it as now playing
+pub fn track_started(track: ScrobbleTrack) {
+    match PLAYING.lock() {
+        Ok(mut guard) => {
+            *guard = Some(Playing {
+                track: track.clone(),
+                scrobbled: false,
+            });
+        }
+        Err(e) => {
+            warn!("Failed to lock scrobble state: {}", e);
+            return;
+        }
+    }
+
+    submit(track, false);
+}
+
+/// Scrobble the current track once its position passes the threshold
+pub fn track_progress(position_secs: u64) {
+    let Ok(mut guard) = PLAYING.lock() else {
+        return;
+    };
+    let Some(playing) = guard.as_mut() else {
+        return;
+    };
+
+    if playing.scrobbled || !is_due(&playing.track, position_secs) {
+        return;
+    }
+
+    playing.scrobbled = true;
+    submit(playing.track.clone(), true);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::testing;
+
+    fn track(length_secs: Option<u64>) -> ScrobbleTrack {
+        ScrobbleTrack {
+            title: "Title".to_string(),
+            artist: "Artist".to_string(),
+            album: None,
+            length_secs,
+            started_at: 1_000,
+        }
+    }
+
+    /// Whether the current track was scrobbled already
+    fn scrobbled() -> bool {
+        PLAYING
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|playing| playing.scrobbled)
+    }
+
+    #[test]
+    fn threshold_is_half_the_length_up_to_four_minutes() {
+        assert_eq!(threshold_secs(Some(200)), 100);
+        assert_eq!(threshold_secs(Some(481)), 240);
+        assert_eq!(threshold_secs(Some(3_600)), MAX_THRESHOLD_SECS);
+        assert_eq!(threshold_secs(None), MAX_THRESHOLD_SECS);
+    }
+
+    #[test]
+    fn tracks_are_due_past_the_threshold() {
+        assert!(!is_due(&track(Some(200)), 99));
+        assert!(is_due(&track(Some(200)), 100));
+        assert!(!is_due(&track(None), 239));
+        assert!(is_due(&track(None), 240));
+    }
+
+    #[test]
+    fn short_tracks_are_never_due() {
+        assert!(!is_due(&track(Some(MIN_TRACK_SECS - 1)), 1_000));
+        assert!(is_due(&track(Some(MIN_TRACK_SECS)), MIN_TRACK_SECS));
+    }
+
+    #[test]
+    fn replaying_a_track_scrobbles_it_again() {
+        // Without services configured nothing is sent anywhere
+        let _state = testing::with_config(Config {
+            scrobble_services: Vec::new(),
+            ..Config::default()
+        });
+
+        track_started(track(Some(200)));
+        track_progress(150);
+        assert!(scrobbled());
+
+        // The second listen of the same track starts over
+        track_started(track(Some(200)));
+        assert!(!scrobbled());
+        track_progress(150);
+        assert!(scrobbled());
+    }
+}
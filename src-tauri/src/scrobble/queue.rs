@@ -0,0 +1,149 @@
+use crate::error::{AppError, Result};
+use crate::scrobble::{ScrobbleService, ScrobbleTrack};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+
+/// Pending scrobbles kept at most, the oldest are dropped beyond that
+const MAX_PENDING: usize = 500;
+
+/// Scrobble that failed to submit, waiting for the service to be reachable again
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct PendingScrobble {
+    service: ScrobbleService,
+    track: ScrobbleTrack,
+}
+
+// Pending scrobbles, oldest first, loaded from disk on first use
+lazy_static::lazy_static! {
+    static ref QUEUE: Mutex<Option<VecDeque<PendingScrobble>>> = Mutex::new(None);
+}
+
+// Set while the queue is being retried, so the same scrobble isn't submitted twice
+static RETRYING: AtomicBool = AtomicBool::new(false);
+
+/// Path of the queue, e.g. `~/.local/share/amusic/scrobble-queue.json`
+fn queue_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("amusic").join("scrobble-queue.json"))
+}
+
+/// Read the queue file
+fn load() -> VecDeque<PendingScrobble> {
+    let Some(path) = queue_path() else {
+        return VecDeque::new();
+    };
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+        return VecDeque::new();
+    };
+
+    match serde_json::from_str::<VecDeque<PendingScrobble>>(&contents) {
+        Ok(pending) => {
+            debug!("Loaded {} pending scrobbles", pending.len());
+            pending
+        }
+        Err(e) => {
+            warn!("Ignoring corrupt scrobble queue {}: {}", path.display(), e);
+            VecDeque::new()
+        }
+    }
+}
+
+/// Write the queue file
+fn save(pending: &VecDeque<PendingScrobble>) -> Result<()> {
+    let path =
+        queue_path().ok_or_else(|| AppError::Application("No data directory available".into()))?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            AppError::Application(format!("Failed to create {}: {}", dir.display(), e))
+        })?;
+    }
+
+    let json = serde_json::to_string(pending)?;
+
+    std::fs::write(&path, json)
+        .map_err(|e| AppError::Application(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+/// Run an update on the queue and persist it
+fn update<T>(update: impl FnOnce(&mut VecDeque<PendingScrobble>) -> T) -> Option<T> {
+    let mut guard = match QUEUE.lock() {
+        Ok(guard) => guard,
+        Err(e) => {
+            warn!("Failed to lock scrobble queue: {}", e);
+            return None;
+        }
+    };
+    let pending = guard.get_or_insert_with(load);
+
+    let result = update(pending);
+    if let Err(e) = save(pending) {
+        warn!("Failed to save scrobble queue: {}", e);
+    }
+    Some(result)
+}
+
+/// Keep a scrobble that failed to submit, to retry once the service is reachable again
+pub fn push(service: ScrobbleService, track: ScrobbleTrack) {
+    update(|pending| {
+        pending.push_back(PendingScrobble { service, track });
+        while pending.len() > MAX_PENDING {
+            if let Some(dropped) = pending.pop_front() {
+                warn!(
+                    "Scrobble queue full, dropping {} - {}",
+                    dropped.track.artist, dropped.track.title
+                );
+            }
+        }
+    });
+}
+
+/// Submit the scrobbles pending for a service, oldest first, stopping at the first failure
+pub fn retry(service: ScrobbleService) {
+    if RETRYING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let tracks: Vec<ScrobbleTrack> = update(|pending| {
+        pending
+            .iter()
+            .filter(|entry| entry.service == service)
+            .map(|entry| entry.track.clone())
+            .collect()
+    })
+    .unwrap_or_default();
+
+    let mut sent = 0;
+    for track in &tracks {
+        match service.submit(track, true) {
+            Some(Ok(())) => sent += 1,
+            Some(Err(e)) => {
+                debug!("Pending scrobble still failing: {}", e);
+                break;
+            }
+            None => break,
+        }
+    }
+
+    if sent > 0 {
+        info!("Submitted {} pending scrobbles to {:?}", sent, service);
+
+        // Drop the ones just submitted, the oldest of this service
+        update(|pending| {
+            let mut remaining = sent;
+            pending.retain(|entry| {
+                if remaining > 0 && entry.service == service {
+                    remaining -= 1;
+                    false
+                } else {
+                    true
+                }
+            });
+        });
+    }
+
+    RETRYING.store(false, Ordering::SeqCst);
+}
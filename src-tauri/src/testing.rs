@@ -0,0 +1,100 @@
+//! Helpers shared by the unit tests
+
+use crate::config::{self, Config};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+// Serializes the tests touching global state: the configuration, the song cache and the presence sink
+static GLOBAL_STATE: Mutex<()> = Mutex::new(());
+
+/// Exclusive access to the global state, restoring the default configuration when dropped
+pub struct GlobalState {
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl Drop for GlobalState {
+    fn drop(&mut self) {
+        config::set(Config::default());
+    }
+}
+
+/// Take the global state for the rest of the test, with the given configuration in use
+/// A test that failed while holding it doesn't fail the others
+pub fn with_config(config: Config) -> GlobalState {
+    let guard = GLOBAL_STATE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    config::set(config);
+
+    GlobalState { _guard: guard }
+}
+
+/// Answer a single request on a loopback server with the body, after a delay
+/// Returns the URL to request, and a receiver for the path and query that were requested
+pub fn serve_once(delay: Duration, body: &'static str) -> (String, mpsc::Receiver<String>) {
+    let server = tiny_http::Server::http("127.0.0.1:0").expect("Failed to start mock server");
+    let url = format!("http://{}/search", server.server_addr());
+    let (requested, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        if let Ok(request) = server.recv() {
+            let _ = requested.send(request.url().to_string());
+            thread::sleep(delay);
+            let _ = request.respond(tiny_http::Response::from_string(body));
+        }
+    });
+
+    (url, receiver)
+}
+
+/// Answer every request with the body on a loopback server keeping connections alive
+/// Returns the URL to request, and the number of TCP connections accepted so far
+pub fn serve_keep_alive(body: &'static str) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to start mock server");
+    let url = format!("http://{}/search", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    let accepted = connections.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            accepted.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || answer_requests(stream, body));
+        }
+    });
+
+    (url, connections)
+}
+
+/// Answer the requests sent over a connection until the client closes it
+fn answer_requests(stream: TcpStream, body: &str) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(stream);
+
+    loop {
+        // Requests without a body end with an empty line
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) if line == "\r\n" => break,
+                Ok(_) => {}
+            }
+        }
+
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        if writer.write_all(response.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
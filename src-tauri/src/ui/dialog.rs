@@ -0,0 +1,40 @@
+use crate::error::AppError;
+use crate::ui::tray;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use tauri_plugin_opener::OpenerExt;
+use tracing::error;
+
+/// Browser requirements of amusic
+const INSTALL_INSTRUCTIONS_URL: &str = "https://github.com/rxtsel/amusic#prerequisites";
+
+/// Log a failure to launch Apple Music, explaining a missing browser in a native dialog
+pub fn report_launch_error(e: &AppError) {
+    error!("{}", e);
+
+    if !matches!(e, AppError::NoBrowser) {
+        return;
+    }
+    let Some(app) = tray::app_handle() else {
+        return;
+    };
+
+    let opener = app.clone();
+    app.dialog()
+        .message(e.to_string())
+        .title("No compatible browser found")
+        .kind(MessageDialogKind::Error)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Install instructions".to_string(),
+            "Close".to_string(),
+        ))
+        .show(move |open_instructions| {
+            if open_instructions {
+                if let Err(e) = opener
+                    .opener()
+                    .open_url(INSTALL_INSTRUCTIONS_URL, None::<&str>)
+                {
+                    error!("Failed to open {}: {}", INSTALL_INSTRUCTIONS_URL, e);
+                }
+            }
+        });
+}
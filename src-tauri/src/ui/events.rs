@@ -0,0 +1,18 @@
+use crate::apple_music::player::TrackInfo;
+use crate::ui::tray;
+use tauri::Emitter;
+use tracing::error;
+
+/// Event sent to the frontend when the track changes or playback starts or stops
+pub const TRACK_CHANGED_EVENT: &str = "track-changed";
+
+/// Send the current track to the frontend
+pub fn emit_track_changed(track: &TrackInfo) {
+    let Some(app) = tray::app_handle() else {
+        return;
+    };
+
+    if let Err(e) = app.emit(TRACK_CHANGED_EVENT, track) {
+        error!("Failed to emit {} event: {}", TRACK_CHANGED_EVENT, e);
+    }
+}
@@ -1,4 +1,12 @@
+pub mod dialog;
+pub mod events;
+pub mod notification;
 pub mod tray;
 
 // Re-export commonly used functions
-pub use tray::setup as setup_tray;
+pub use dialog::report_launch_error;
+pub use events::emit_track_changed;
+pub use notification::notify_track;
+pub use tray::{
+    refresh_menu as refresh_tray_menu, set_tooltip as set_tray_tooltip, setup as setup_tray,
+};
@@ -0,0 +1,58 @@
+use crate::config;
+use crate::error::{AppError, Result};
+use crate::utils::http;
+use notify_rust::Notification;
+use std::fs;
+use std::path::PathBuf;
+use tracing::{debug, warn};
+
+/// Path the artwork is downloaded to, notification icons must be local files
+fn artwork_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("amusic").join("notification-artwork"))
+}
+
+/// Download the artwork so it can be used as the notification icon
+fn download_artwork(artwork_url: &str) -> Result<PathBuf> {
+    let path = artwork_path()
+        .ok_or_else(|| AppError::Application("No cache directory for the artwork".into()))?;
+
+    let bytes = http::client().get(artwork_url).send()?.bytes()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, &bytes)?;
+
+    Ok(path)
+}
+
+/// Show a "Now playing" notification for a new track, when enabled in the config
+pub fn notify_track(title: &str, artist: &str, artwork_url: Option<&str>) {
+    if !config::get().notifications_enabled {
+        return;
+    }
+
+    let body = format!("{} - {}", artist, title);
+    let artwork_url = artwork_url.map(str::to_string);
+
+    // Downloading the artwork must not hold the presence update back
+    std::thread::spawn(move || {
+        let mut notification = Notification::new();
+        notification
+            .summary("Now playing")
+            .body(&body)
+            .appname("amusic");
+
+        if let Some(artwork_url) = artwork_url {
+            match download_artwork(&artwork_url) {
+                Ok(path) => {
+                    notification.icon(&path.to_string_lossy());
+                }
+                Err(e) => debug!("Could not download notification artwork: {}", e),
+            }
+        }
+
+        if let Err(e) = notification.show() {
+            warn!("Failed to show track notification: {}", e);
+        }
+    });
+}
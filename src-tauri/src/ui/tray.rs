@@ -1,46 +1,267 @@
 use crate::apple_music;
+use crate::apple_music::{history, player};
+use crate::commands;
 use crate::discord;
-use crate::error::Result;
+use crate::error::{AppError, Result};
+use crate::ui::dialog;
+use std::sync::OnceLock;
 use tauri::{
-    menu::{Menu, MenuItem},
+    menu::{IsMenuItem, Menu, MenuItem, Submenu},
     tray::TrayIconBuilder,
-    App,
+    App, AppHandle, Manager, Wry,
 };
+use tauri_plugin_opener::OpenerExt;
+use tracing::{debug, error, info, warn};
+
+/// Identifier of the application tray icon
+const TRAY_ID: &str = "main";
+
+/// Tooltip shown while nothing is playing
+const DEFAULT_TOOLTIP: &str = "Apple Music";
+
+/// Prefix of the menu item ids for recent tracks, followed by the track URL
+const RECENT_ITEM_PREFIX: &str = "recent:";
+
+// Handle used to rebuild the tray menu from background threads
+static APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
+
+/// Build the tray menu with the recent tracks submenu, the playback controls, the open, restart, presence toggle, Discord retry and quit items
+fn build_menu<M: Manager<Wry>>(manager: &M) -> tauri::Result<Menu<Wry>> {
+    // One item per recent track, opening its Apple Music URL when clicked
+    let recent_items = history::recent()
+        .into_iter()
+        .map(|track| {
+            MenuItem::with_id(
+                manager,
+                format!("{}{}", RECENT_ITEM_PREFIX, track.apple_music_url),
+                format!("{} – {}", track.title, track.artist),
+                true,
+                None::<&str>,
+            )
+        })
+        .collect::<tauri::Result<Vec<_>>>()?;
+    let recent_refs: Vec<&dyn IsMenuItem<Wry>> = recent_items
+        .iter()
+        .map(|item| item as &dyn IsMenuItem<Wry>)
+        .collect();
+    let recent_menu =
+        Submenu::with_items(manager, "Recent", !recent_refs.is_empty(), &recent_refs)?;
+
+    // Only offered while Discord presence is disabled
+    let retry_item = MenuItem::with_id(
+        manager,
+        "retry_discord",
+        "Discord unavailable – Retry",
+        true,
+        None::<&str>,
+    )?;
+
+    // Label reflects what clicking the item will do
+    let toggle_label = if discord::is_presence_enabled() {
+        "Pause Discord Presence"
+    } else {
+        "Resume Discord Presence"
+    };
+    let toggle_item =
+        MenuItem::with_id(manager, "toggle_presence", toggle_label, true, None::<&str>)?;
+
+    let previous_item = MenuItem::with_id(manager, "previous", "Previous", true, None::<&str>)?;
+    // Generic label, so it doesn't have to follow the playback status
+    let play_pause_item =
+        MenuItem::with_id(manager, "play_pause", "Play/Pause", true, None::<&str>)?;
+    let next_item = MenuItem::with_id(manager, "next", "Next", true, None::<&str>)?;
+
+    let open_item = MenuItem::with_id(
+        manager,
+        "ensure_open",
+        "Open Apple Music",
+        true,
+        None::<&str>,
+    )?;
+
+    let restart_item = MenuItem::with_id(
+        manager,
+        "restart",
+        "Restart Apple Music",
+        true,
+        None::<&str>,
+    )?;
+
+    let quit_item = MenuItem::with_id(manager, "quit", "Quit Apple Music", true, None::<&str>)?;
+
+    if discord::is_disabled() {
+        Menu::with_items(
+            manager,
+            &[
+                &recent_menu,
+                &previous_item,
+                &play_pause_item,
+                &next_item,
+                &open_item,
+                &restart_item,
+                &toggle_item,
+                &retry_item,
+                &quit_item,
+            ],
+        )
+    } else {
+        Menu::with_items(
+            manager,
+            &[
+                &recent_menu,
+                &previous_item,
+                &play_pause_item,
+                &next_item,
+                &open_item,
+                &restart_item,
+                &toggle_item,
+                &quit_item,
+            ],
+        )
+    }
+}
+
+/// Handle of the running app, once the tray is set up
+pub(crate) fn app_handle() -> Option<&'static AppHandle> {
+    APP_HANDLE.get()
+}
+
+/// Rebuild the tray menu, e.g. after the recent tracks changed
+pub fn refresh_menu() {
+    let Some(app) = APP_HANDLE.get() else {
+        return;
+    };
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+
+    match build_menu(app) {
+        Ok(menu) => {
+            if let Err(e) = tray.set_menu(Some(menu)) {
+                error!("Failed to update tray menu: {}", e);
+            }
+        }
+        Err(e) => error!("Failed to rebuild tray menu: {}", e),
+    }
+}
+
+/// Show the current track in the tray tooltip, or the default tooltip when nothing is playing
+pub fn set_tooltip(track: Option<(&str, &str)>) {
+    let Some(app) = APP_HANDLE.get() else {
+        return;
+    };
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+
+    let tooltip = match track {
+        Some((title, artist)) => format!("{} — {}", artist, title),
+        None => DEFAULT_TOOLTIP.to_string(),
+    };
+    if let Err(e) = tray.set_tooltip(Some(tooltip)) {
+        error!("Failed to update tray tooltip: {}", e);
+    }
+}
 
 /// Setup tray icon and menu
 pub fn setup(app: &App) -> Result<()> {
-    // Create tray menu items - only quit option
-    let quit_item = MenuItem::with_id(app, "quit", "Quit Apple Music", true, None::<&str>)
-        .expect("Failed to create 'Quit' menu item");
+    let _ = APP_HANDLE.set(app.handle().clone());
 
-    // Create tray menu with just the quit item
-    let menu = Menu::with_items(app, &[&quit_item]).expect("Failed to create tray menu");
+    let menu = build_menu(app)
+        .map_err(|e| AppError::Application(format!("Failed to create tray menu: {}", e)))?;
 
     // Create the tray icon with menu
-    let _tray = TrayIconBuilder::new()
+    let _tray = TrayIconBuilder::with_id(TRAY_ID)
         .icon(
             app.default_window_icon()
                 .expect("Failed to get default window icon")
                 .clone(),
         )
-        .tooltip("Apple Music")
+        .tooltip(DEFAULT_TOOLTIP)
         .menu(&menu)
         // Always show the menu on right click
         .on_menu_event(|app, event| match event.id.as_ref() {
             "quit" => {
-                println!("Quit menu item clicked");
+                info!("Quit menu item clicked");
 
-                // Clear Discord presence before exiting
-                let _ = discord::clear_presence();
-
-                // Kill Apple Music process
-                apple_music::kill_apple_music();
+                // Clear Discord presence and kill Apple Music before exiting
+                apple_music::shutdown();
 
                 // Then exit the app
                 app.exit(0);
             }
-            _ => {
-                println!("Unhandled menu item: {:?}", event.id);
+            "next" => {
+                info!("Next menu item clicked");
+
+                // Finding the player goes over D-Bus, keep the event loop free
+                std::thread::spawn(|| {
+                    if let Err(e) = player::skip_next() {
+                        warn!("Could not skip to the next track: {}", e);
+                    }
+                });
+            }
+            "play_pause" => {
+                info!("Play/Pause menu item clicked");
+
+                std::thread::spawn(|| {
+                    if let Err(e) = player::toggle_playback() {
+                        warn!("Could not toggle playback: {}", e);
+                    }
+                });
+            }
+            "previous" => {
+                info!("Previous menu item clicked");
+
+                std::thread::spawn(|| {
+                    if let Err(e) = player::skip_previous() {
+                        warn!("Could not go back to the previous track: {}", e);
+                    }
+                });
+            }
+            "ensure_open" => {
+                info!("Open Apple Music menu item clicked");
+
+                // Launching waits for the browser to start, keep the event loop free
+                std::thread::spawn(|| {
+                    if let Err(e) = apple_music::ensure_apple_music_open() {
+                        dialog::report_launch_error(&e);
+                    }
+                });
+            }
+            "restart" => {
+                info!("Restart Apple Music menu item clicked");
+
+                // Waits for the old browser to exit, keep the event loop free
+                std::thread::spawn(|| {
+                    if let Err(e) = apple_music::restart_apple_music() {
+                        dialog::report_launch_error(&e);
+                    }
+                });
+            }
+            "toggle_presence" => {
+                let enabled = !discord::is_presence_enabled();
+                if let Err(e) = commands::set_presence_enabled(enabled) {
+                    error!("Failed to clear presence: {}", e);
+                }
+            }
+            "retry_discord" => {
+                info!("Retry Discord menu item clicked");
+
+                // Connecting may take a few attempts, keep the event loop free
+                std::thread::spawn(|| {
+                    if let Err(e) = commands::retry_discord() {
+                        error!("{}", e);
+                    }
+                });
+            }
+            id => {
+                if let Some(url) = id.strip_prefix(RECENT_ITEM_PREFIX) {
+                    if let Err(e) = app.opener().open_url(url, None::<&str>) {
+                        error!("Failed to open {}: {}", url, e);
+                    }
+                } else {
+                    debug!("Unhandled menu item: {:?}", event.id);
+                }
             }
         })
         .build(app)
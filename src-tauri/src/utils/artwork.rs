@@ -1,48 +1,796 @@
-use crate::config::constants::{APPLE_MUSIC_URL, ITUNES_SEARCH_API_URL};
+use crate::config::constants::UNKNOWN_ARTIST;
+use crate::config::{self, urls};
+use crate::error::{AppError, Result};
+use crate::utils::{artwork_cache, artwork_server, http};
+use regex::Regex;
 use reqwest::blocking::Client;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{debug, error, warn};
 use urlencoding::encode;
 
-/// Search for the album artwork on iTunes
-pub fn get_artwork_url(artist: &str, title: &str) -> Option<String> {
-    let client = Client::new();
+/// MusicBrainz recording search endpoint
+const MUSICBRAINZ_SEARCH_URL: &str = "https://musicbrainz.org/ws/2/recording";
+
+/// Cover Art Archive endpoint serving release covers by MBID
+const COVER_ART_ARCHIVE_URL: &str = "https://coverartarchive.org/release";
+
+/// Odesli API resolving a streaming service URL to a song.link page
+const SONGLINK_API_URL: &str = "https://api.song.link/v1-alpha.1/links";
+
+/// Artwork sizes served by the iTunes CDN, in pixels
+const ITUNES_ARTWORK_SIZES: &[u32] = &[
+    100, 200, 300, 400, 500, 600, 800, 1000, 1024, 1200, 1400, 2000, 3000,
+];
+
+/// Artist names used by compilations instead of the track artist
+const VARIOUS_ARTISTS: &[&str] = &[
+    "various artists",
+    "various",
+    "va",
+    "varios artistas",
+    "artistes divers",
+    "verschiedene interpreten",
+];
+
+lazy_static::lazy_static! {
+    // Size segment of iTunes artwork URLs, e.g. "/100x100bb.jpg"
+    static ref ARTWORK_SIZE_SEGMENT: Regex =
+        Regex::new(r"/\d+x\d+(\w*)\.(\w+)$").expect("Invalid artwork size regex");
+}
+
+// Requests sent to iTunes and MusicBrainz, reported by the diagnostics
+static FETCHES: AtomicU64 = AtomicU64::new(0);
+
+/// Requests sent to iTunes and MusicBrainz since startup
+/// Cache hits and art taken from the MPRIS metadata aren't requests, so they don't count
+pub fn fetch_count() -> u64 {
+    FETCHES.load(Ordering::Relaxed)
+}
+
+/// Source of album artwork, as named in the config
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArtworkSource {
+    /// Art URL embedded in the MPRIS metadata
+    Mpris,
+    /// iTunes search API
+    Itunes,
+    /// MusicBrainz release lookup with the Cover Art Archive
+    MusicBrainz,
+}
+
+impl ArtworkSource {
+    /// Parse a source from its configured name
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "mpris" => Ok(ArtworkSource::Mpris),
+            "itunes" => Ok(ArtworkSource::Itunes),
+            "musicbrainz" => Ok(ArtworkSource::MusicBrainz),
+            _ => Err(AppError::Application(format!(
+                "Unknown artwork provider '{}', expected one of: mpris, itunes, musicbrainz",
+                name
+            ))),
+        }
+    }
+}
+
+/// Anything that can find the artwork of a track
+pub trait ArtworkProvider {
+    /// URL of the track's artwork, `None` when nothing was found
+    fn artwork_url(&self, artist: &str, title: &str, album: Option<&str>) -> Option<String>;
+}
+
+/// Art URL embedded in the MPRIS metadata of the current track
+pub struct MprisProvider {
+    pub art_url: Option<String>,
+}
+
+impl ArtworkProvider for MprisProvider {
+    fn artwork_url(&self, _artist: &str, _title: &str, _album: Option<&str>) -> Option<String> {
+        usable_mpris_art_url(self.art_url.as_deref())
+    }
+}
+
+/// iTunes search API, backed by the disk cache
+pub struct ItunesProvider;
+
+impl ArtworkProvider for ItunesProvider {
+    fn artwork_url(&self, artist: &str, title: &str, _album: Option<&str>) -> Option<String> {
+        cached_artwork_url(artist, title)
+    }
+}
+
+/// MusicBrainz release lookup with the Cover Art Archive
+pub struct MusicBrainzProvider;
+
+impl ArtworkProvider for MusicBrainzProvider {
+    fn artwork_url(&self, artist: &str, title: &str, album: Option<&str>) -> Option<String> {
+        match musicbrainz_artwork_url(artist, title, album) {
+            Ok(artwork_url) => artwork_url,
+            Err(e) => {
+                debug!("{}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Providers tried in order, the first hit wins
+pub struct ProviderChain {
+    providers: Vec<Box<dyn ArtworkProvider>>,
+}
+
+impl ProviderChain {
+    /// Chain providers, tried in the given order
+    pub fn new(providers: Vec<Box<dyn ArtworkProvider>>) -> Self {
+        ProviderChain { providers }
+    }
+}
+
+impl ArtworkProvider for ProviderChain {
+    fn artwork_url(&self, artist: &str, title: &str, album: Option<&str>) -> Option<String> {
+        self.providers
+            .iter()
+            .find_map(|provider| provider.artwork_url(artist, title, album))
+    }
+}
+
+/// Parse artwork provider names, keeping their order
+pub fn parse_providers(names: &[String]) -> Result<Vec<ArtworkSource>> {
+    names
+        .iter()
+        .map(|name| ArtworkSource::from_name(name))
+        .collect()
+}
+
+/// Parse the configured artwork providers, keeping their order
+pub fn configured_providers() -> Result<Vec<ArtworkSource>> {
+    parse_providers(&config::get().artwork_providers)
+}
+
+/// Whether a provider is in the configured chain
+fn is_configured(source: ArtworkSource) -> bool {
+    configured_providers()
+        .map(|providers| providers.contains(&source))
+        .unwrap_or(false)
+}
+
+/// Build the chain of configured providers, the MPRIS one using the given art URL
+pub fn configured_chain(mpris_art_url: Option<&str>) -> ProviderChain {
+    let mpris = || -> Box<dyn ArtworkProvider> {
+        Box::new(MprisProvider {
+            art_url: mpris_art_url.map(str::to_string),
+        })
+    };
+
+    // Offline, the art embedded in the metadata is all we can use
+    if config::get().offline {
+        return ProviderChain::new(vec![mpris()]);
+    }
+
+    let sources = match configured_providers() {
+        Ok(sources) => sources,
+        Err(e) => {
+            error!("{}", e);
+            Vec::new()
+        }
+    };
+
+    ProviderChain::new(
+        sources
+            .into_iter()
+            .map(|source| match source {
+                ArtworkSource::Mpris => mpris(),
+                ArtworkSource::Itunes => Box::new(ItunesProvider) as Box<dyn ArtworkProvider>,
+                ArtworkSource::MusicBrainz => Box::new(MusicBrainzProvider),
+            })
+            .collect(),
+    )
+}
+
+/// Keep an MPRIS art URL only if Discord can load it
+fn usable_mpris_art_url(mpris_art_url: Option<&str>) -> Option<String> {
+    let url = mpris_art_url?;
+
+    // Discord can only load artwork over http(s), not local file:// paths or data URIs
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Some(url.to_string())
+    } else if url.starts_with("file://") && config::get().serve_local_artwork {
+        artwork_server::serve_file(url)
+    } else {
+        debug!("Ignoring MPRIS art URL Discord can't load: {}", url);
+        None
+    }
+}
+
+/// The MPRIS art URL, when the MPRIS provider is enabled and Discord can load it
+/// The art embedded in the metadata belongs to the exact track, so it beats any search
+pub fn mpris_artwork(mpris_art_url: Option<&str>) -> Option<String> {
+    if is_configured(ArtworkSource::Mpris) || config::get().offline {
+        usable_mpris_art_url(mpris_art_url)
+    } else {
+        None
+    }
+}
+
+/// Try each configured artwork provider in order and return the first hit
+pub fn find_artwork(
+    artist: &str,
+    title: &str,
+    album: Option<&str>,
+    mpris_art_url: Option<&str>,
+) -> Option<String> {
+    configured_chain(mpris_art_url).artwork_url(artist, title, album)
+}
+
+/// Try each configured search provider in order, e.g. iTunes then MusicBrainz,
+/// and return the first hit
+pub fn get_artwork_url_with_fallback(
+    artist: &str,
+    title: &str,
+    album: Option<&str>,
+) -> Option<String> {
+    find_artwork(artist, title, album, None)
+}
+
+/// Resolve the release of a recording on MusicBrainz and return its Cover Art Archive front cover
+fn musicbrainz_artwork_url(
+    artist: &str,
+    title: &str,
+    album: Option<&str>,
+) -> Result<Option<String>> {
+    // Lucene query, quotes inside the values would end the phrases early
+    let phrase = |value: &str| value.replace('"', "");
+    let mut query = format!(
+        "recording:\"{}\" AND artist:\"{}\"",
+        phrase(title),
+        phrase(artist)
+    );
+    if let Some(album) = album.filter(|album| !album.trim().is_empty()) {
+        query.push_str(&format!(" AND release:\"{}\"", phrase(album)));
+    }
+
+    let url = format!(
+        "{}?query={}&fmt=json&limit=1",
+        MUSICBRAINZ_SEARCH_URL,
+        encode(&query)
+    );
+    FETCHES.fetch_add(1, Ordering::Relaxed);
+    let json = http::client()
+        .get(&url)
+        .send()
+        .map_err(|e| AppError::Network(format!("Error making request to MusicBrainz: {}", e)))?
+        .json::<serde_json::Value>()?;
+
+    let Some(mbid) = json["recordings"][0]["releases"][0]["id"].as_str() else {
+        debug!("No MusicBrainz release found for {} - {}", artist, title);
+        return Ok(None);
+    };
+
+    // Not every release has a cover, the archive answers 404 for those
+    let artwork_url = format!("{}/{}/front-500", COVER_ART_ARCHIVE_URL, mbid);
+    let response = http::client().head(&artwork_url).send().map_err(|e| {
+        AppError::Network(format!(
+            "Error making request to the Cover Art Archive: {}",
+            e
+        ))
+    })?;
+    if !response.status().is_success() {
+        debug!("No Cover Art Archive front cover for release {}", mbid);
+        return Ok(None);
+    }
+
+    Ok(Some(artwork_url))
+}
+
+/// Result of an iTunes search made with the title only
+#[derive(Clone, Debug, Default)]
+pub struct TitleLookup {
+    /// Artwork URL of the top result, if any
+    pub artwork_url: Option<String>,
+    /// Artist name of the top result, if any
+    pub artist: Option<String>,
+}
+
+/// Check whether MPRIS failed to report a usable artist
+pub fn is_unknown_artist(artist: &str) -> bool {
+    let artist = artist.trim();
+    artist.is_empty() || artist.eq_ignore_ascii_case(UNKNOWN_ARTIST)
+}
+
+/// Check whether the track belongs to a various-artists compilation
+pub fn is_compilation(artist: &str, album_artist: Option<&str>) -> bool {
+    let is_various = |name: &str| VARIOUS_ARTISTS.contains(&name.trim().to_lowercase().as_str());
+
+    if is_various(artist) {
+        return true;
+    }
+
+    // The album belongs to someone else than the track artist
+    match album_artist {
+        Some(album_artist) => {
+            is_various(album_artist)
+                || (!album_artist.trim().is_empty()
+                    && !artist
+                        .to_lowercase()
+                        .contains(&album_artist.trim().to_lowercase()))
+        }
+        None => false,
+    }
+}
+
+/// Query the iTunes API and return the top result, if any
+fn search_itunes(term: &str, entity: Option<&str>) -> Option<serde_json::Value> {
+    match try_search_itunes(term, entity) {
+        Ok(result) => result,
+        Err(e) => {
+            debug!("{}", e);
+            None
+        }
+    }
+}
+
+/// Build the URL of an iTunes search for the top result of a term
+fn build_itunes_search_url(
+    api_url: &str,
+    term: &str,
+    entity: Option<&str>,
+    storefront: Option<&str>,
+) -> String {
+    let mut itunes_url = format!("{}?term={}&media=music&limit=1", api_url, encode(term));
+    if let Some(entity) = entity {
+        itunes_url.push_str(&format!("&entity={}", entity));
+    }
+    if let Some(storefront) = storefront {
+        itunes_url.push_str(&format!("&country={}", storefront));
+    }
+    itunes_url
+}
+
+/// Query the iTunes API, telling failed requests apart from searches without results
+fn try_search_itunes(term: &str, entity: Option<&str>) -> Result<Option<serde_json::Value>> {
+    if config::get().offline {
+        return Err(AppError::Network("Offline mode, skipping iTunes".into()));
+    }
 
     // Build the query for iTunes API
-    let query = format!("{} {}", artist, title);
-    let encoded_query = encode(&query);
-    let itunes_url = format!(
-        "{}?term={}&media=music&limit=1",
-        ITUNES_SEARCH_API_URL, encoded_query
+    let itunes_url = build_itunes_search_url(
+        &urls::itunes_api_url(),
+        term,
+        entity,
+        urls::storefront().as_deref(),
     );
 
+    fetch_top_result(http::client(), &itunes_url, term)
+}
+
+/// Request an iTunes search and return its top result, if any
+fn fetch_top_result(
+    client: &Client,
+    itunes_url: &str,
+    term: &str,
+) -> Result<Option<serde_json::Value>> {
     // Make the request
-    let response = match client.get(&itunes_url).send() {
-        Ok(resp) => resp,
+    FETCHES.fetch_add(1, Ordering::Relaxed);
+    let response = client.get(itunes_url).send().map_err(|e| {
+        // A slow iTunes must not hold the presence back, it falls back to the default image
+        if e.is_timeout() {
+            warn!("iTunes request timed out for: {}", term);
+        }
+        AppError::Network(format!("Error making request to iTunes: {}", e))
+    })?;
+
+    // Analyze the response
+    let json = response.json::<serde_json::Value>()?;
+    Ok(json["results"]
+        .as_array()
+        .and_then(|results| results.first().cloned()))
+}
+
+/// Check that an artwork size is one the iTunes CDN serves
+pub fn validate_size(size: u32) -> Result<()> {
+    if ITUNES_ARTWORK_SIZES.contains(&size) {
+        return Ok(());
+    }
+
+    let sizes: Vec<String> = ITUNES_ARTWORK_SIZES
+        .iter()
+        .map(|size| size.to_string())
+        .collect();
+    Err(AppError::Application(format!(
+        "Unsupported artwork size {}, expected one of: {}",
+        size,
+        sizes.join(", ")
+    )))
+}
+
+/// Rewrite the size segment of an iTunes artwork URL, keeping its suffix and format
+fn resize_artwork_url(artwork_url: &str, size: u32) -> String {
+    ARTWORK_SIZE_SEGMENT
+        .replace(artwork_url, format!("/{size}x{size}${{1}}.${{2}}"))
+        .into_owned()
+}
+
+/// Extract a large artwork URL from an iTunes search result
+fn artwork_from_result(result: &serde_json::Value) -> Option<String> {
+    let artwork_url = result["artworkUrl100"]
+        .as_str()
+        .or_else(|| result["artworkUrl60"].as_str())?;
+
+    // Get a larger version by rewriting the size segment to the configured size
+    Some(resize_artwork_url(artwork_url, config::get().artwork_size))
+}
+
+/// Search for the album artwork on iTunes
+pub fn get_artwork_url(artist: &str, title: &str) -> Option<String> {
+    let query = format!("{} {}", artist, title);
+
+    if let Some(artwork_url) = search_itunes(&query, None)
+        .as_ref()
+        .and_then(artwork_from_result)
+    {
+        return Some(artwork_url);
+    }
+
+    debug!("No artwork found on iTunes");
+    None
+}
+
+/// Search for the album artwork, checking the disk cache before iTunes
+pub fn cached_artwork_url(artist: &str, title: &str) -> Option<String> {
+    if let Some(artwork_url) = artwork_cache::get(artist, title) {
+        debug!("Using cached artwork lookup for {} - {}", artist, title);
+        return artwork_url;
+    }
+
+    // Only cache answers from iTunes, a failed request may succeed next time
+    let query = format!("{} {}", artist, title);
+    match try_search_itunes(&query, None) {
+        Ok(result) => {
+            let artwork_url = result.as_ref().and_then(artwork_from_result);
+            if artwork_url.is_none() {
+                debug!("No artwork found on iTunes");
+            }
+            artwork_cache::put(artist, title, artwork_url.clone());
+            artwork_url
+        }
         Err(e) => {
-            println!("Error making request to iTunes: {}", e);
-            return None;
+            debug!("{}", e);
+            None
         }
+    }
+}
+
+/// Search iTunes by title only, used when MPRIS did not report an artist
+/// Nothing is searched unless iTunes is one of the configured providers
+pub fn lookup_by_title(title: &str) -> TitleLookup {
+    if !is_configured(ArtworkSource::Itunes) {
+        debug!("iTunes is not a configured artwork provider, not searching by title");
+        return TitleLookup::default();
+    }
+
+    let Some(result) = search_itunes(title, None) else {
+        debug!("No iTunes match found for title: {}", title);
+        return TitleLookup::default();
     };
 
-    // Analyze the response
-    if let Ok(json) = response.json::<serde_json::Value>() {
-        if let Some(results) = json["results"].as_array() {
-            if !results.is_empty() {
-                if let Some(artwork_url) = results[0]["artworkUrl100"].as_str() {
-                    // Get a larger version by replacing 100x100 with 600x600
-                    let larger_artwork = artwork_url.replace("100x100", "600x600");
-                    return Some(larger_artwork);
-                }
+    TitleLookup {
+        artwork_url: artwork_from_result(&result),
+        artist: result["artistName"].as_str().map(str::to_string),
+    }
+}
+
+/// Search iTunes for an album's artwork, used for compilations, checking the disk cache first
+/// Nothing is searched unless iTunes is one of the configured providers
+pub fn get_album_artwork_url(album: &str) -> Option<String> {
+    if !is_configured(ArtworkSource::Itunes) {
+        debug!("iTunes is not a configured artwork provider, not searching by album");
+        return None;
+    }
+
+    if let Some(artwork_url) = artwork_cache::get_album(album) {
+        debug!("Using cached album artwork lookup for {}", album);
+        return artwork_url;
+    }
+
+    // Only cache answers from iTunes, a failed request may succeed next time
+    match try_search_itunes(album, Some("album")) {
+        Ok(result) => {
+            let artwork_url = result.as_ref().and_then(artwork_from_result);
+            if artwork_url.is_none() {
+                debug!("No album artwork found on iTunes for: {}", album);
             }
+            artwork_cache::put_album(album, artwork_url.clone());
+            artwork_url
+        }
+        Err(e) => {
+            debug!("{}", e);
+            None
         }
     }
+}
 
-    println!("No artwork found on iTunes");
-    None
+/// Resolve a track to its song.link page, opening it on whichever service the listener uses
+fn try_songlink_url(artist: &str, title: &str) -> Result<Option<String>> {
+    // Odesli needs a link on one service to start from, take the iTunes one
+    let query = format!("{} {}", artist, title);
+    let Some(track_url) = try_search_itunes(&query, Some("song"))?
+        .and_then(|result| result["trackViewUrl"].as_str().map(str::to_string))
+    else {
+        return Ok(None);
+    };
+
+    let mut songlink_url = format!("{}?url={}", SONGLINK_API_URL, encode(&track_url));
+    if let Some(storefront) = urls::storefront() {
+        songlink_url.push_str(&format!("&userCountry={}", storefront.to_uppercase()));
+    }
+
+    let response = http::client()
+        .get(&songlink_url)
+        .send()
+        .map_err(|e| AppError::Network(format!("Error making request to song.link: {}", e)))?;
+    if !response.status().is_success() {
+        return Err(AppError::Network(format!(
+            "song.link answered with {}",
+            response.status()
+        )));
+    }
+
+    let json = response.json::<serde_json::Value>()?;
+    Ok(json["pageUrl"].as_str().map(str::to_string))
+}
+
+/// song.link page of a track, backed by the disk cache
+pub fn get_songlink_url(artist: &str, title: &str) -> Option<String> {
+    if let Some(songlink_url) = artwork_cache::get_songlink(artist, title) {
+        debug!("Using cached song.link lookup for {} - {}", artist, title);
+        return songlink_url;
+    }
+
+    // Only cache answers, a failed request may succeed next time
+    match try_songlink_url(artist, title) {
+        Ok(songlink_url) => {
+            if songlink_url.is_none() {
+                debug!("No song.link page found for {} - {}", artist, title);
+            }
+            artwork_cache::put_songlink(artist, title, songlink_url.clone());
+            songlink_url
+        }
+        Err(e) => {
+            debug!("{}", e);
+            None
+        }
+    }
+}
+
+/// URL the "Play" button opens: the song.link page when enabled and found,
+/// otherwise an Apple Music search
+pub fn get_play_url(title: &str, artist: &str) -> String {
+    if config::get().songlink_enabled {
+        if let Some(songlink_url) = get_songlink_url(artist, title) {
+            return songlink_url;
+        }
+    }
+
+    get_apple_music_search_url(title, artist)
 }
 
 /// Generate search URL for Apple Music
 pub fn get_apple_music_search_url(title: &str, artist: &str) -> String {
-    let apple_music_query = format!("{} {}", title, artist);
-    let encoded_query = encode(&apple_music_query);
-    format!("{}/search?term={}", APPLE_MUSIC_URL, encoded_query)
+    build_apple_music_search_url(
+        &config::get().apple_music_url,
+        urls::storefront().as_deref(),
+        title,
+        artist,
+    )
+}
+
+/// Build the URL of an Apple Music search for a track, in the storefront when given
+fn build_apple_music_search_url(
+    base_url: &str,
+    storefront: Option<&str>,
+    title: &str,
+    artist: &str,
+) -> String {
+    let query = format!("{} {}", title, artist);
+    let encoded_query = encode(&query);
+    let base_url = base_url.trim_end_matches('/');
+    match storefront {
+        Some(storefront) => format!("{}/{}/search?term={}", base_url, storefront, encoded_query),
+        None => format!("{}/search?term={}", base_url, encoded_query),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::testing;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    const API_URL: &str = "https://itunes.apple.com/search";
+
+    #[test]
+    fn itunes_search_url_for_plain_track() {
+        assert_eq!(
+            build_itunes_search_url(API_URL, "Daft Punk One More Time", None, None),
+            "https://itunes.apple.com/search?term=Daft%20Punk%20One%20More%20Time&media=music&limit=1"
+        );
+    }
+
+    #[test]
+    fn itunes_search_url_encodes_reserved_characters() {
+        let url = build_itunes_search_url(API_URL, "Simon & Garfunkel #1", None, None);
+        assert_eq!(
+            url,
+            "https://itunes.apple.com/search?term=Simon%20%26%20Garfunkel%20%231&media=music&limit=1"
+        );
+    }
+
+    #[test]
+    fn itunes_search_url_encodes_non_ascii() {
+        let url = build_itunes_search_url(API_URL, "Björk Jóga", None, None);
+        assert_eq!(
+            url,
+            "https://itunes.apple.com/search?term=Bj%C3%B6rk%20J%C3%B3ga&media=music&limit=1"
+        );
+
+        let url = build_itunes_search_url(API_URL, "宇多田ヒカル", None, None);
+        assert!(url.contains("term=%E5%AE%87%E5%A4%9A%E7%94%B0"));
+    }
+
+    #[test]
+    fn itunes_search_url_with_entity_and_country() {
+        assert_eq!(
+            build_itunes_search_url(API_URL, "Discovery", Some("album"), Some("jp")),
+            "https://itunes.apple.com/search?term=Discovery&media=music&limit=1&entity=album&country=jp"
+        );
+    }
+
+    #[test]
+    fn apple_music_search_url_encodes_title_and_artist() {
+        assert_eq!(
+            build_apple_music_search_url(
+                "https://music.apple.com",
+                None,
+                "Rock & Roll #9",
+                "Björk"
+            ),
+            "https://music.apple.com/search?term=Rock%20%26%20Roll%20%239%20Bj%C3%B6rk"
+        );
+    }
+
+    #[test]
+    fn apple_music_search_url_in_storefront() {
+        assert_eq!(
+            build_apple_music_search_url("https://music.apple.com/", Some("gb"), "Song", "Artist"),
+            "https://music.apple.com/gb/search?term=Song%20Artist"
+        );
+    }
+
+    /// Queries a fake provider got, as "artist - title"
+    type Queries = Rc<RefCell<Vec<String>>>;
+
+    /// Provider answering with a fixed URL and recording the queries it got
+    struct FakeProvider {
+        artwork_url: Option<&'static str>,
+        queries: Queries,
+    }
+
+    impl ArtworkProvider for FakeProvider {
+        fn artwork_url(&self, artist: &str, title: &str, _album: Option<&str>) -> Option<String> {
+            self.queries
+                .borrow_mut()
+                .push(format!("{} - {}", artist, title));
+            self.artwork_url.map(str::to_string)
+        }
+    }
+
+    /// A fake provider, along with the queries it will get
+    fn fake(artwork_url: Option<&'static str>) -> (Box<dyn ArtworkProvider>, Queries) {
+        let queries = Queries::default();
+        let provider = FakeProvider {
+            artwork_url,
+            queries: queries.clone(),
+        };
+        (Box::new(provider), queries)
+    }
+
+    #[test]
+    fn chain_stops_at_the_first_hit() {
+        let (miss, miss_queries) = fake(None);
+        let (hit, hit_queries) = fake(Some("https://example.com/hit.jpg"));
+        let (unused, unused_queries) = fake(Some("https://example.com/unused.jpg"));
+
+        let chain = ProviderChain::new(vec![miss, hit, unused]);
+
+        assert_eq!(
+            chain.artwork_url("Artist", "Title", None).as_deref(),
+            Some("https://example.com/hit.jpg")
+        );
+        assert_eq!(*miss_queries.borrow(), ["Artist - Title"]);
+        assert_eq!(*hit_queries.borrow(), ["Artist - Title"]);
+        assert!(unused_queries.borrow().is_empty());
+    }
+
+    #[test]
+    fn chain_without_hits_finds_nothing() {
+        let (miss, _) = fake(None);
+        let chain = ProviderChain::new(vec![miss]);
+        assert_eq!(chain.artwork_url("Artist", "Title", None), None);
+
+        let empty = ProviderChain::new(Vec::new());
+        assert_eq!(empty.artwork_url("Artist", "Title", None), None);
+    }
+
+    #[test]
+    fn mpris_provider_only_keeps_urls_discord_can_load() {
+        let _state = testing::with_config(Config::default());
+        let provider = |art_url: &str| MprisProvider {
+            art_url: Some(art_url.to_string()),
+        };
+
+        assert_eq!(
+            provider("https://example.com/cover.jpg")
+                .artwork_url("Artist", "Title", None)
+                .as_deref(),
+            Some("https://example.com/cover.jpg")
+        );
+        assert_eq!(
+            provider("file:///tmp/cover.jpg").artwork_url("Artist", "Title", None),
+            None
+        );
+        assert_eq!(
+            provider("data:image/png;base64,AAAA").artwork_url("Artist", "Title", None),
+            None
+        );
+        assert_eq!(
+            MprisProvider { art_url: None }.artwork_url("Artist", "Title", None),
+            None
+        );
+    }
+
+    #[test]
+    fn itunes_is_not_searched_unless_configured() {
+        let (itunes_url, requests) =
+            testing::serve_once(Duration::ZERO, r#"{"resultCount":0,"results":[]}"#);
+        let _state = testing::with_config(Config {
+            itunes_api_url: itunes_url,
+            artwork_providers: vec!["mpris".to_string(), "musicbrainz".to_string()],
+            ..Config::default()
+        });
+
+        let lookup = lookup_by_title("Title");
+        assert_eq!(lookup.artwork_url, None);
+        assert_eq!(lookup.artist, None);
+        assert_eq!(get_album_artwork_url("Album"), None);
+
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[test]
+    fn itunes_result_is_read_from_the_response() {
+        let (url, _) = testing::serve_once(
+            Duration::ZERO,
+            r#"{"resultCount":1,"results":[{"artistName":"Artist"}]}"#,
+        );
+        let client = http::build_client(Duration::from_secs(5));
+        let fetches = fetch_count();
+
+        let result = fetch_top_result(&client, &url, "Artist Title").expect("Request failed");
+        assert_eq!(result.unwrap()["artistName"], "Artist");
+        // Other tests may fetch meanwhile, but this one must have counted
+        assert!(fetch_count() > fetches);
+    }
+
+    #[test]
+    fn slow_itunes_response_times_out() {
+        let (url, _) =
+            testing::serve_once(Duration::from_secs(2), r#"{"resultCount":0,"results":[]}"#);
+        let client = http::build_client(Duration::from_millis(200));
+
+        let result = fetch_top_result(&client, &url, "Artist Title");
+        assert!(matches!(result, Err(AppError::Network(_))));
+    }
 }
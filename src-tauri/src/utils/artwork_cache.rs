@@ -0,0 +1,181 @@
+use crate::config;
+use crate::error::{AppError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+/// Artwork or link lookup result stored on disk
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct CacheEntry {
+    /// Artwork or song.link URL, `None` when the lookup found nothing
+    #[serde(alias = "artwork_url")]
+    url: Option<String>,
+    /// When the lookup was made, in UNIX seconds
+    fetched_at: i64,
+}
+
+/// Prefix keeping song.link lookups apart from artwork lookups of the same track
+const SONGLINK_KEY_PREFIX: &str = "songlink\n";
+
+/// Prefix keeping album artwork lookups apart from track lookups
+const ALBUM_KEY_PREFIX: &str = "album\n";
+
+// Artwork and song.link lookups keyed by artist and title, or by album, loaded from disk on first use
+lazy_static::lazy_static! {
+    static ref ARTWORK_CACHE: Mutex<Option<HashMap<String, CacheEntry>>> = Mutex::new(None);
+}
+
+/// Path of the artwork cache, e.g. `~/.cache/amusic/artwork.json`
+fn cache_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("amusic").join("artwork.json"))
+}
+
+/// Current UNIX time in seconds
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Cache key of a track, ignoring case and surrounding whitespace
+fn cache_key(artist: &str, title: &str) -> String {
+    format!(
+        "{}\n{}",
+        artist.trim().to_lowercase(),
+        title.trim().to_lowercase()
+    )
+}
+
+/// Whether an entry is still fresh, negative results expire sooner
+fn is_fresh(entry: &CacheEntry, now: i64) -> bool {
+    let config = config::get();
+    let ttl = if entry.url.is_some() {
+        config.artwork_cache_ttl_secs
+    } else {
+        config.artwork_negative_cache_ttl_secs
+    };
+
+    now - entry.fetched_at < ttl as i64
+}
+
+/// Read the cache file, dropping expired entries
+fn load() -> HashMap<String, CacheEntry> {
+    let Some(path) = cache_path() else {
+        return HashMap::new();
+    };
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+        return HashMap::new();
+    };
+
+    match serde_json::from_str::<HashMap<String, CacheEntry>>(&contents) {
+        Ok(mut entries) => {
+            let now = unix_now();
+            entries.retain(|_, entry| is_fresh(entry, now));
+            debug!("Loaded {} cached artwork lookups", entries.len());
+            entries
+        }
+        Err(e) => {
+            warn!("Ignoring corrupt artwork cache {}: {}", path.display(), e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Write the cache file
+fn save(entries: &HashMap<String, CacheEntry>) -> Result<()> {
+    let path =
+        cache_path().ok_or_else(|| AppError::Application("No cache directory available".into()))?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            AppError::Application(format!("Failed to create {}: {}", dir.display(), e))
+        })?;
+    }
+
+    let json = serde_json::to_string(entries)?;
+
+    std::fs::write(&path, json)
+        .map_err(|e| AppError::Application(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+/// Look an entry up in the cache
+fn get_entry(key: &str) -> Option<Option<String>> {
+    let mut guard = ARTWORK_CACHE.lock().ok()?;
+    let entries = guard.get_or_insert_with(load);
+
+    entries
+        .get(key)
+        .filter(|entry| is_fresh(entry, unix_now()))
+        .map(|entry| entry.url.clone())
+}
+
+/// Store an entry and persist the cache
+fn put_entry(key: String, url: Option<String>) {
+    let Ok(mut guard) = ARTWORK_CACHE.lock() else {
+        return;
+    };
+    let entries = guard.get_or_insert_with(load);
+
+    entries.insert(
+        key,
+        CacheEntry {
+            url,
+            fetched_at: unix_now(),
+        },
+    );
+
+    if let Err(e) = save(entries) {
+        warn!("Failed to save artwork cache: {}", e);
+    }
+}
+
+/// Look a track up in the cache
+/// Returns `None` on a miss, `Some(None)` when a recent lookup found no artwork
+pub fn get(artist: &str, title: &str) -> Option<Option<String>> {
+    get_entry(&cache_key(artist, title))
+}
+
+/// Store a lookup result and persist the cache
+pub fn put(artist: &str, title: &str, artwork_url: Option<String>) {
+    put_entry(cache_key(artist, title), artwork_url);
+}
+
+/// Look the song.link URL of a track up in the cache
+/// Returns `None` on a miss, `Some(None)` when a recent lookup found no link
+pub fn get_songlink(artist: &str, title: &str) -> Option<Option<String>> {
+    get_entry(&format!(
+        "{}{}",
+        SONGLINK_KEY_PREFIX,
+        cache_key(artist, title)
+    ))
+}
+
+/// Store a song.link lookup result and persist the cache
+pub fn put_songlink(artist: &str, title: &str, songlink_url: Option<String>) {
+    put_entry(
+        format!("{}{}", SONGLINK_KEY_PREFIX, cache_key(artist, title)),
+        songlink_url,
+    );
+}
+
+/// Look the artwork of an album up in the cache
+/// Returns `None` on a miss, `Some(None)` when a recent lookup found no artwork
+pub fn get_album(album: &str) -> Option<Option<String>> {
+    get_entry(&format!(
+        "{}{}",
+        ALBUM_KEY_PREFIX,
+        album.trim().to_lowercase()
+    ))
+}
+
+/// Store an album artwork lookup result and persist the cache
+pub fn put_album(album: &str, artwork_url: Option<String>) {
+    put_entry(
+        format!("{}{}", ALBUM_KEY_PREFIX, album.trim().to_lowercase()),
+        artwork_url,
+    );
+}
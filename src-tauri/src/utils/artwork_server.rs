@@ -0,0 +1,126 @@
+use crate::error::{AppError, Result};
+use std::sync::{Mutex, OnceLock};
+use tiny_http::{Header, Response, Server};
+use tracing::{debug, error, info, warn};
+
+/// Path the current artwork is served at
+const ARTWORK_PATH: &str = "/art.jpg";
+
+/// Artwork bytes currently served, with their content type
+struct ServedArtwork {
+    bytes: Vec<u8>,
+    content_type: &'static str,
+}
+
+// Artwork of the current track, replaced on every track change
+lazy_static::lazy_static! {
+    static ref ARTWORK: Mutex<Option<ServedArtwork>> = Mutex::new(None);
+}
+
+// Port of the server, set once it is listening
+static PORT: OnceLock<Option<u16>> = OnceLock::new();
+
+/// Content type of an image file, guessed from its extension
+fn content_type(path: &str) -> &'static str {
+    let path = path.to_ascii_lowercase();
+    if path.ends_with(".png") {
+        "image/png"
+    } else if path.ends_with(".webp") {
+        "image/webp"
+    } else {
+        "image/jpeg"
+    }
+}
+
+/// Answer requests for the current artwork until the process exits
+fn serve(server: Server) {
+    for request in server.incoming_requests() {
+        // Ignore the cache-busting query, there is only one artwork
+        let is_artwork = request.url().split('?').next() == Some(ARTWORK_PATH);
+
+        let result = match ARTWORK.lock() {
+            Ok(guard) => match (&*guard, is_artwork) {
+                (Some(artwork), true) => {
+                    let mut response = Response::from_data(artwork.bytes.clone());
+                    if let Ok(header) =
+                        Header::from_bytes("Content-Type", artwork.content_type.as_bytes())
+                    {
+                        response.add_header(header);
+                    }
+                    request.respond(response)
+                }
+                _ => request.respond(Response::empty(404)),
+            },
+            Err(e) => {
+                error!("Failed to lock served artwork mutex: {}", e);
+                request.respond(Response::empty(500))
+            }
+        };
+
+        if let Err(e) = result {
+            debug!("Failed to answer artwork request: {}", e);
+        }
+    }
+}
+
+/// Start the server on an ephemeral loopback port
+fn start() -> Result<u16> {
+    let server = Server::http("127.0.0.1:0")
+        .map_err(|e| AppError::Application(format!("Failed to start artwork server: {}", e)))?;
+    let port = server
+        .server_addr()
+        .to_ip()
+        .map(|addr| addr.port())
+        .ok_or_else(|| AppError::Application("Artwork server is not listening on TCP".into()))?;
+
+    std::thread::spawn(move || serve(server));
+    info!("Serving local artwork on http://127.0.0.1:{}", port);
+
+    Ok(port)
+}
+
+/// Port of the artwork server, started on first use
+pub fn port() -> Option<u16> {
+    *PORT.get_or_init(|| match start() {
+        Ok(port) => Some(port),
+        Err(e) => {
+            warn!("{}", e);
+            None
+        }
+    })
+}
+
+/// Serve a local `file://` artwork and return its loopback URL
+/// The URL changes with the artwork, so Discord doesn't keep showing the previous one
+pub fn serve_file(file_url: &str) -> Option<String> {
+    let encoded_path = file_url.strip_prefix("file://")?;
+    let path = urlencoding::decode(encoded_path).ok()?;
+
+    let bytes = match std::fs::read(path.as_ref()) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            debug!("Failed to read local artwork {}: {}", path, e);
+            return None;
+        }
+    };
+    let port = port()?;
+    let version = format!("{:x}", md5::compute(&bytes));
+
+    match ARTWORK.lock() {
+        Ok(mut guard) => {
+            *guard = Some(ServedArtwork {
+                bytes,
+                content_type: content_type(&path),
+            })
+        }
+        Err(e) => {
+            error!("Failed to lock served artwork mutex: {}", e);
+            return None;
+        }
+    }
+
+    Some(format!(
+        "http://127.0.0.1:{}{}?v={}",
+        port, ARTWORK_PATH, version
+    ))
+}
@@ -0,0 +1,55 @@
+use crate::config;
+use reqwest::blocking::Client;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::warn;
+
+/// Timeout for connecting to remote APIs, in seconds
+const CONNECT_TIMEOUT_SECS: u64 = 5;
+
+/// User agent sent with every request, MusicBrainz rejects anonymous clients
+const USER_AGENT: &str = concat!("amusic/", env!("CARGO_PKG_VERSION"));
+
+// HTTP client shared by every request, so connections are kept alive between tracks
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Get the shared HTTP client, building it on first use
+pub fn client() -> &'static Client {
+    HTTP_CLIENT.get_or_init(|| build_client(Duration::from_secs(config::get().timeout_secs)))
+}
+
+/// Build an HTTP client whose requests give up after the timeout
+pub fn build_client(timeout: Duration) -> Client {
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+        .timeout(timeout)
+        .build()
+        .unwrap_or_else(|e| {
+            warn!("Failed to build HTTP client, using defaults: {}", e);
+            Client::new()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn repeated_lookups_reuse_the_connection() {
+        let (url, connections) = testing::serve_keep_alive(r#"{"resultCount":0,"results":[]}"#);
+
+        for _ in 0..2 {
+            let body = client()
+                .get(&url)
+                .send()
+                .and_then(|response| response.text())
+                .expect("Request failed");
+            assert_eq!(body, r#"{"resultCount":0,"results":[]}"#);
+        }
+
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+}
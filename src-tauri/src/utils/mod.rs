@@ -1 +1,4 @@
 pub mod artwork;
+pub mod artwork_cache;
+pub mod artwork_server;
+pub mod http;